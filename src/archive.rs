@@ -0,0 +1,203 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use chrono::{Local, NaiveDate, TimeZone, Utc};
+
+use crate::{dataservice::DataService, db::Storage, period::Period};
+
+/// Closes every budget period before the current one.
+///
+/// Each closed period gets a summary of its totals, then its bookings are moved into the archive.
+/// The data stays locked throughout, so a booking another instance adds meanwhile can't end up in
+/// the archive without being in its period's summary. Returns the number of archived bookings.
+pub fn rollover(storage: &dyn Storage) -> Result<usize> {
+    let mut archived = 0;
+    storage.with_lock(&mut || {
+        archived = close_periods(storage)?;
+        Ok(())
+    })?;
+    Ok(archived)
+}
+
+fn close_periods(storage: &dyn Storage) -> Result<usize> {
+    let mut ds = DataService::default();
+    ds.load_data(storage)?;
+    ds.current_period();
+    let current = ds.period;
+
+    let mut closed: BTreeSet<Period> = storage
+        .get_expenses()?
+        .iter()
+        .map(|b| b.local_date())
+        .filter(|d| *d < current.start)
+        .map(|d| ds.period_containing(d))
+        .collect();
+
+    // Summaries are kept gapless, so periods without any bookings get one as well.
+    let resume = storage
+        .get_period_summaries()?
+        .iter()
+        .map(|s| ds.period_containing(s.start).next())
+        .max();
+    if let Some(mut period) = closed
+        .iter()
+        .next()
+        .copied()
+        .into_iter()
+        .chain(resume)
+        .min()
+    {
+        while period.start < current.start {
+            closed.insert(period);
+            period = period.next();
+        }
+    }
+
+    for period in closed {
+        storage.set_period_summary(ds.summarize(period)?)?;
+    }
+
+    let midnight = current.start.and_hms_opt(0, 0, 0).unwrap_or_default();
+    let cutoff = Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|d| d.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&midnight));
+    storage.archive_expenses_before(cutoff)
+}
+
+/// Brings closed periods up to date after bookings dated `dates` were added, changed or deleted.
+///
+/// Bookings of closed periods are moved into the archive and the summaries of the periods of
+/// `dates` are recalculated. Dates in the current period or later need nothing.
+pub fn resummarize(storage: &dyn Storage, dates: &[NaiveDate]) -> Result<()> {
+    storage.with_lock(&mut || update_summaries(storage, dates))
+}
+
+fn update_summaries(storage: &dyn Storage, dates: &[NaiveDate]) -> Result<()> {
+    let mut ds = DataService::default();
+    ds.load_data(storage)?;
+    ds.current_period();
+    let current = ds.period;
+    let closed: BTreeSet<Period> = dates
+        .iter()
+        .filter(|d| **d < current.start)
+        .map(|d| ds.period_containing(*d))
+        .collect();
+    if closed.is_empty() {
+        return Ok(());
+    }
+
+    close_periods(storage)?;
+    ds.load_data(storage)?;
+    for period in closed {
+        storage.set_period_summary(ds.summarize(period)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::handle_command,
+        db::{JsonStorage, MemoryStorage},
+        journal,
+        model::{BookingType::*, BudgetBooking},
+        money::Money,
+        testutil::TempDir,
+    };
+    use std::{sync::mpsc, thread, time::Duration};
+
+    #[test]
+    fn test_rollover_archives_closed_periods() {
+        let storage = MemoryStorage::default();
+        let current = Period::current(1);
+        let mut old = BudgetBooking::new("Old", Expense, "tt", Money::from_minor(500));
+        old.date = Utc.from_utc_datetime(&current.previous().start.and_hms_opt(12, 0, 0).unwrap());
+        storage.add_expense(old).unwrap();
+        storage
+            .add_expense(BudgetBooking::new(
+                "New",
+                Expense,
+                "tt",
+                Money::from_minor(100),
+            ))
+            .unwrap();
+
+        assert_eq!(rollover(&storage).unwrap(), 1);
+        assert_eq!(storage.get_expenses().unwrap()[0].name, "New");
+        assert_eq!(storage.get_expenses_archive().unwrap()[0].name, "Old");
+
+        let summaries = storage.get_period_summaries().unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].start, current.previous().start);
+        assert_eq!(summaries[0].budget_spent, Money::from_minor(500));
+
+        // nothing left to do on the next run
+        assert_eq!(rollover(&storage).unwrap(), 0);
+        assert_eq!(storage.get_period_summaries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_backdated_booking_updates_closed_period() {
+        let storage = MemoryStorage::default();
+        handle_command("ac Food f", &storage).unwrap();
+        let previous = Period::current(1).previous();
+        rollover(&storage).unwrap();
+        let spent = |storage: &MemoryStorage| {
+            let summaries = storage.get_period_summaries().unwrap();
+            let summary = summaries.iter().find(|s| s.start == previous.start);
+            summary.map(|s| s.budget_spent)
+        };
+
+        let cmd = format!("ae Receipt f 7.50 {}", previous.start);
+        handle_command(&cmd, &storage).unwrap();
+        assert!(storage.get_expenses().unwrap().is_empty());
+        assert_eq!(storage.get_expenses_archive().unwrap()[0].name, "Receipt");
+        assert_eq!(spent(&storage), Some(Money::from_minor(750)));
+
+        handle_command("ee 1 amount=9", &storage).unwrap();
+        assert_eq!(spent(&storage), Some(Money::from_minor(900)));
+        journal::undo(&storage).unwrap();
+        journal::undo(&storage).unwrap();
+        assert!(storage.get_expenses_archive().unwrap().is_empty());
+        assert_eq!(spent(&storage), Some(Money::ZERO));
+    }
+
+    #[test]
+    fn test_rollover_waits_for_other_writers() {
+        let dir = TempDir::new("rollover");
+        let storage = JsonStorage::new(&dir).unwrap();
+        let previous = Period::current(1).previous();
+
+        // another instance books into the closed period while the rollover starts
+        let (locked, wait) = mpsc::channel();
+        let path = dir.to_path_buf();
+        let other = thread::spawn(move || {
+            let storage = JsonStorage::new(&path).unwrap();
+            storage
+                .with_lock(&mut || {
+                    locked.send(()).unwrap();
+                    thread::sleep(Duration::from_millis(300));
+                    let mut late =
+                        BudgetBooking::new("Late", Expense, "tt", Money::from_minor(500));
+                    late.date =
+                        Utc.from_utc_datetime(&previous.start.and_hms_opt(12, 0, 0).unwrap());
+                    storage.add_expense(late)?;
+                    Ok(())
+                })
+                .unwrap();
+        });
+        wait.recv().unwrap();
+        assert_eq!(rollover(&storage).unwrap(), 1);
+        other.join().unwrap();
+
+        let summaries = storage.get_period_summaries().unwrap();
+        let summary = summaries
+            .iter()
+            .find(|s| s.start == previous.start)
+            .unwrap();
+        assert_eq!(summary.budget_spent, Money::from_minor(500));
+    }
+}
@@ -0,0 +1,232 @@
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::Result;
+
+use crate::{
+    db::Storage,
+    journal::{self, Change},
+    model::{category_path, Category},
+};
+
+/// An inconsistency between categories and the items referring to them, e.g. left behind by
+/// editing the data files by hand.
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// Bookings or recurring items refer to a token no category has.
+    UnknownCategory {
+        token: String,
+        bookings: usize,
+        recurring: usize,
+    },
+    /// Several categories share a token, lowest id first.
+    DuplicateCategory { token: String, ids: Vec<u64> },
+    /// The parent of a category is unknown, or the category itself is above its parent.
+    InvalidParent { id: u64, parent: String },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::UnknownCategory {
+                token,
+                bookings,
+                recurring,
+            } => write!(
+                f,
+                "Unknown category '{}' used by {} booking(s) and {} recurring item(s)",
+                token, bookings, recurring
+            ),
+            Problem::DuplicateCategory { token, ids } => {
+                let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+                write!(
+                    f,
+                    "Categories {} share the token '{}'",
+                    ids.join(", "),
+                    token
+                )
+            }
+            Problem::InvalidParent { id, parent } => write!(
+                f,
+                "Category {} has the unknown or circular parent '{}'",
+                id, parent
+            ),
+        }
+    }
+}
+
+pub fn find_problems(storage: &dyn Storage) -> Result<Vec<Problem>> {
+    let all = storage.get_categories()?;
+    let mut categories: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    for category in &all {
+        categories
+            .entry(category.token.clone())
+            .or_default()
+            .push(category.id);
+    }
+
+    // (bookings, recurring) per unknown token
+    let mut unknown: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    for booking in storage
+        .get_expenses()?
+        .into_iter()
+        .chain(storage.get_expenses_archive()?)
+    {
+        if !categories.contains_key(&booking.category_token) {
+            unknown.entry(booking.category_token).or_default().0 += 1;
+        }
+    }
+    for recurring in storage.get_recurring()? {
+        if !categories.contains_key(&recurring.category_token) {
+            unknown.entry(recurring.category_token).or_default().1 += 1;
+        }
+    }
+
+    let mut problems: Vec<Problem> = unknown
+        .into_iter()
+        .map(|(token, (bookings, recurring))| Problem::UnknownCategory {
+            token,
+            bookings,
+            recurring,
+        })
+        .collect();
+    for (token, mut ids) in categories {
+        if ids.len() > 1 {
+            ids.sort_unstable();
+            problems.push(Problem::DuplicateCategory { token, ids });
+        }
+    }
+    for category in &all {
+        if let Some(parent) = &category.parent {
+            // In a loop of parents the category is above its own parent
+            let circular = category_path(&all, parent).contains(&category.token.as_str());
+            if !all.iter().any(|c| &c.token == parent) || circular {
+                problems.push(Problem::InvalidParent {
+                    id: category.id,
+                    parent: parent.clone(),
+                });
+            }
+        }
+    }
+    Ok(problems)
+}
+
+/// Adds a category named after each unknown token, keeps only the oldest of categories sharing a
+/// token and moves categories with an invalid parent to the top level. The added and moved
+/// categories are a single change, so it can be undone from the UI. Removing duplicates can't be
+/// undone, the storage doesn't accept a token twice.
+pub fn repair(problems: &[Problem], storage: &dyn Storage) -> Result<()> {
+    let categories = storage.get_categories()?;
+    let mut removed = vec![];
+    for problem in problems {
+        if let Problem::DuplicateCategory { ids, .. } = problem {
+            for id in &ids[1..] {
+                storage.delete_category(*id)?;
+                removed.push(*id);
+            }
+        }
+    }
+
+    let mut changes = vec![];
+    for problem in problems {
+        match problem {
+            Problem::UnknownCategory { token, .. } => {
+                changes.push(Change::AddCategory(Category::new(token, token)));
+            }
+            Problem::InvalidParent { id, .. } if !removed.contains(id) => {
+                if let Some(before) = categories.iter().find(|c| c.id == *id) {
+                    let mut after = before.clone();
+                    after.parent = None;
+                    changes.push(Change::UpdateCategory {
+                        before: before.clone(),
+                        after,
+                    });
+                }
+            }
+            Problem::InvalidParent { .. } | Problem::DuplicateCategory { .. } => {}
+        }
+    }
+    if changes.is_empty() {
+        return Ok(());
+    }
+    journal::apply(Change::batch(changes), storage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::handle_command;
+    use crate::db::JsonStorage;
+    use crate::testutil::TempDir;
+    use std::fs;
+
+    #[test]
+    fn test_find_and_repair() {
+        let dir = TempDir::new("check");
+        let storage = JsonStorage::new(&dir).unwrap();
+        for command in [
+            "ac Food f",
+            "ac Extra x",
+            "ac Gym g",
+            "ae Coffee x 3",
+            "ae Tea x 2",
+            "are Gym g 30",
+            "ac Rent r",
+        ] {
+            handle_command(command, &storage).unwrap();
+        }
+        // as if the data files had been edited by hand, the storage itself refuses duplicates
+        fs::write(
+            dir.join("data_categories.json"),
+            r#"[
+                {"id": 1, "token": "f", "name": "Food"},
+                {"id": 4, "token": "r", "name": "Rent"},
+                {"id": 5, "token": "f", "name": "Fuel"},
+                {"id": 6, "token": "p", "name": "Power", "parent": "u"}
+            ]"#,
+        )
+        .unwrap();
+
+        let problems = find_problems(&storage).unwrap();
+        assert_eq!(
+            problems,
+            vec![
+                Problem::UnknownCategory {
+                    token: "g".to_string(),
+                    bookings: 0,
+                    recurring: 1
+                },
+                Problem::UnknownCategory {
+                    token: "x".to_string(),
+                    bookings: 2,
+                    recurring: 0
+                },
+                Problem::DuplicateCategory {
+                    token: "f".to_string(),
+                    ids: vec![1, 5]
+                },
+                Problem::InvalidParent {
+                    id: 6,
+                    parent: "u".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            problems[2].to_string(),
+            "Categories 1, 5 share the token 'f'"
+        );
+
+        repair(&problems, &storage).unwrap();
+        assert!(find_problems(&storage).unwrap().is_empty());
+        let categories = storage.get_categories().unwrap();
+        assert_eq!(categories.len(), 5);
+        assert!(categories.iter().all(|c| c.parent.is_none()));
+        assert!(categories.iter().any(|c| c.token == "x"));
+        assert!(categories.iter().all(|c| c.name != "Fuel"));
+
+        // only the removed duplicate stays removed
+        journal::undo(&storage).unwrap();
+        let remaining = find_problems(&storage).unwrap();
+        assert_eq!(remaining.len(), 3);
+        assert!(remaining.iter().all(|p| problems.contains(p)));
+    }
+}
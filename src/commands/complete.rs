@@ -0,0 +1,196 @@
+use anyhow::Result;
+
+use super::parser::{quote, tokenize, Token};
+use super::registry::{self, Arg, ArgKind, Command};
+use super::{COMMANDS, PRESETS, SETTINGS};
+use crate::db::Storage;
+
+/// Candidates for the word at the end of a command line.
+#[derive(Debug, Default, PartialEq)]
+pub struct Completion {
+    /// Byte offset of the completed word, the line up to here stays as it is.
+    pub start: usize,
+    /// Replacements for the word, quoted where needed.
+    pub candidates: Vec<String>,
+}
+
+/// Completes command names, category tokens, recurrence keywords, currencies and the names of
+/// earlier bookings, depending on which argument the last word of `line` is.
+pub fn complete(line: &str, storage: &dyn Storage) -> Result<Completion> {
+    let start = word_start(line);
+    let (done, word) = line.split_at(start);
+    let tokens = match tokenize(done) {
+        Ok(tokens) => tokens,
+        Err(_) => return Ok(Completion::default()),
+    };
+
+    let candidates = match tokens.split_first() {
+        None => {
+            let names = COMMANDS
+                .iter()
+                .flat_map(|c| c.aliases.iter().copied().chain([c.name]))
+                .map(|n| n.to_string());
+            matching(names, unquoted(word))
+        }
+        Some((first, args)) => match registry::find(&first.value) {
+            Some(command) => complete_argument(command, args, word, storage)?,
+            None => vec![],
+        },
+    };
+    Ok(Completion { start, candidates })
+}
+
+fn complete_argument(
+    command: &Command,
+    given: &[Token],
+    word: &str,
+    storage: &dyn Storage,
+) -> Result<Vec<String>> {
+    if let Some((key, value)) = word
+        .split_once('=')
+        .filter(|(k, _)| !k.contains(['"', '\'']))
+    {
+        let values = values_for(key, storage)?;
+        return Ok(matching(values, unquoted(value))
+            .into_iter()
+            .map(|v| format!("{}={}", key, v))
+            .collect());
+    }
+
+    // Required arguments not given by name are filled by position, optional ones follow in any
+    // order.
+    let named = |a: &&Arg| given.iter().any(|t| t.key.as_deref() == Some(a.name));
+    let required: Vec<&Arg> = command
+        .args
+        .iter()
+        .filter(|a| a.kind == ArgKind::Required)
+        .filter(|a| !named(a))
+        .collect();
+    let position = given.iter().filter(|t| t.key.is_none()).count();
+
+    let mut values = vec![];
+    match required.get(position) {
+        Some(arg) => values.extend(values_for(arg.name, storage)?),
+        None => {
+            for arg in command.args {
+                match arg.kind {
+                    ArgKind::Optional => values.extend(values_for(arg.name, storage)?),
+                    ArgKind::Named if !named(&arg) => values.push(format!("{}=", arg.name)),
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(matching(values, unquoted(word)))
+}
+
+/// Known values of an argument, empty for free text like amounts.
+fn values_for(arg: &str, storage: &dyn Storage) -> Result<Vec<String>> {
+    Ok(match arg {
+        "command" => COMMANDS.iter().map(|c| c.name.to_string()).collect(),
+        "category" | "to" | "parent" => storage
+            .get_categories()?
+            .into_iter()
+            .map(|c| c.token)
+            .collect(),
+        "date" => vec!["today".to_string(), "yesterday".to_string()],
+        "period" => vec!["next".to_string(), "previous".to_string()],
+        "recurrence" => PRESETS.iter().map(|p| p.to_string()).collect(),
+        "key" => SETTINGS.iter().map(|s| s.to_string()).collect(),
+        "currency" => {
+            let mut codes = vec![storage.get_setting_base_currency()?];
+            codes.extend(
+                storage
+                    .get_exchange_rates()?
+                    .into_iter()
+                    .map(|r| r.currency),
+            );
+            codes
+        }
+        "name" => {
+            let mut names: Vec<String> = storage
+                .get_expenses()?
+                .into_iter()
+                .chain(storage.get_expenses_archive()?)
+                .map(|b| b.name)
+                .chain(storage.get_recurring()?.into_iter().map(|r| r.name))
+                .collect();
+            names.sort();
+            names
+        }
+        _ => vec![],
+    })
+}
+
+/// The distinct `values` starting with `prefix`, ignoring case, quoted for the command line.
+fn matching(values: impl IntoIterator<Item = String>, prefix: &str) -> Vec<String> {
+    let prefix = prefix.to_lowercase();
+    let mut found: Vec<String> = vec![];
+    for value in values {
+        if !value.is_empty() && value.to_lowercase().starts_with(&prefix) {
+            let quoted = if value.ends_with('=') {
+                value
+            } else {
+                quote(&value)
+            };
+            if !found.contains(&quoted) {
+                found.push(quoted);
+            }
+        }
+    }
+    found
+}
+
+fn unquoted(word: &str) -> &str {
+    word.trim_start_matches(['"', '\''])
+}
+
+/// Byte offset after the last whitespace outside of quotes.
+fn word_start(line: &str) -> usize {
+    let mut start = 0;
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, c) if c.is_whitespace() => start = i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::handle_command;
+    use crate::db::MemoryStorage;
+
+    #[test]
+    fn test_complete() {
+        let storage = MemoryStorage::default();
+        handle_command("ac Food f", &storage).unwrap();
+        handle_command("ac Fun fun", &storage).unwrap();
+        handle_command("ac Rent r", &storage).unwrap();
+        handle_command(r#"ae "Coffee beans" f 12.90"#, &storage).unwrap();
+        handle_command("ax USD 0.9", &storage).unwrap();
+        let candidates = |line: &str| complete(line, &storage).unwrap().candidates;
+
+        assert_eq!(
+            candidates("add-r"),
+            vec!["add-recurring-expense", "add-recurring-income"]
+        );
+        assert_eq!(candidates("ae Co"), vec![r#""Coffee beans""#]);
+        assert_eq!(candidates("ae \"co"), vec![r#""Coffee beans""#]);
+        assert_eq!(candidates("ae Coffee f"), vec!["f", "fun"]);
+        assert_eq!(candidates("ae category=f Coffee "), Vec::<String>::new());
+        assert_eq!(candidates("are Gym r 30 q"), vec!["quarterly"]);
+        assert_eq!(candidates("are Gym r 30 u"), vec!["USD", "until="]);
+        assert_eq!(candidates("ee 1 category=r"), vec!["category=r"]);
+        assert_eq!(candidates("help edit-c"), vec!["edit-category"]);
+        assert!(candidates("xx ").is_empty());
+
+        let completion = complete("ae \"Coffee beans\" f", &storage).unwrap();
+        assert_eq!(completion.start, 18);
+    }
+}
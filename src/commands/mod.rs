@@ -0,0 +1,1462 @@
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Days, Local, NaiveDate, Weekday};
+
+use crate::{
+    archive,
+    db::Storage,
+    journal::{self, Change},
+    model::{
+        category_path, Assignment, BookingType, BudgetBooking, Category, ExchangeRate, Record,
+        Recurrence, RecurringBooking, RecurringType, Setting,
+    },
+    money::Money,
+    period::Period,
+};
+
+mod complete;
+mod parser;
+mod registry;
+
+pub use complete::{complete, Completion};
+pub use parser::quote;
+use parser::{Args, ParseError};
+pub use registry::find as find_command;
+use registry::{Arg, Command};
+
+const ID: &str = "an id from the # column";
+const AMOUNT: &str = "an amount like 12.50";
+const DATE: &str = "a date like 2026-10-14, yesterday, -3d or mon";
+
+const NAME: Arg = Arg::required("name", "what it is, quoted if it contains spaces");
+const CATEGORY: Arg = Arg::required("category", "token of the category");
+const ITEM_AMOUNT: Arg = Arg::required("amount", AMOUNT);
+const CURRENCY: Arg = Arg::optional("currency", "currency code, defaults to the base currency");
+const BOOKING_DATE: Arg = Arg::optional(
+    "date",
+    "2026-10-14, today, yesterday, -3d or the last mon..sun, defaults to now",
+);
+const PRESET: Arg = Arg::optional(
+    "recurrence",
+    "weekly, biweekly, monthly (default), quarterly, semiannual or yearly",
+);
+const EVERY: Arg = Arg::named("every", "custom interval like 4w, 3m or 2y");
+const UNTIL: Arg = Arg::named("until", "last date like 2027-06-30, empty for none");
+const COUNT: Arg = Arg::named("count", "number of occurrences, empty for no limit");
+const START: Arg = Arg::named(
+    "start",
+    "first due date like 2026-11-01, later ones follow from it, defaults to today",
+);
+/// Keys the `set` command accepts.
+const SETTINGS: [&str; 5] = [
+    "Base_Currency",
+    "Currency_Symbol",
+    "Period_Start_Day",
+    "Envelope_Mode",
+    "Archive_Split_Yearly",
+];
+/// Recurrence keywords understood by `recurrence_preset`.
+const PRESETS: [&str; 6] = [
+    "weekly",
+    "biweekly",
+    "monthly",
+    "quarterly",
+    "semiannual",
+    "yearly",
+];
+
+const ROW_ID: Arg = Arg::required("id", "id from the # column");
+const NEW_NAME: Arg = Arg::named("name", "new name");
+const NEW_AMOUNT: Arg = Arg::named("amount", "new amount");
+const NEW_CATEGORY: Arg = Arg::named("category", "new category token");
+const NEW_DATE: Arg = Arg::named("date", "new date, moves the booking into its period");
+const NEW_CURRENCY: Arg = Arg::named("currency", "new currency, empty for the base currency");
+const LIMIT: Arg = Arg::named("limit", "monthly spending limit, empty for none");
+const PARENT: Arg = Arg::named(
+    "parent",
+    "token of the category it belongs to, empty for a top-level one",
+);
+
+/// Every command, the parser and the help are generated from this list.
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "add-expense",
+        aliases: &["ae"],
+        args: &[NAME, CATEGORY, ITEM_AMOUNT, BOOKING_DATE, CURRENCY],
+        help: "Book an expense, today unless a date is given",
+        run: add_expense,
+    },
+    Command {
+        name: "add-income",
+        aliases: &["ai"],
+        args: &[NAME, CATEGORY, ITEM_AMOUNT, BOOKING_DATE, CURRENCY],
+        help: "Book a one-off income like a refund or a gift",
+        run: add_income,
+    },
+    Command {
+        name: "add-recurring-expense",
+        aliases: &["are"],
+        args: &[
+            NAME,
+            CATEGORY,
+            ITEM_AMOUNT,
+            PRESET,
+            CURRENCY,
+            EVERY,
+            UNTIL,
+            COUNT,
+            START,
+        ],
+        help: "Add an expense that is booked on every due date",
+        run: add_recurring_expense,
+    },
+    Command {
+        name: "add-recurring-income",
+        aliases: &["ari"],
+        args: &[
+            NAME,
+            CATEGORY,
+            ITEM_AMOUNT,
+            PRESET,
+            CURRENCY,
+            EVERY,
+            UNTIL,
+            COUNT,
+            START,
+        ],
+        help: "Add an income that is booked on every due date",
+        run: add_recurring_income,
+    },
+    Command {
+        name: "add-category",
+        aliases: &["ac"],
+        args: &[
+            Arg::required("name", "display name"),
+            Arg::required("token", "short token used by bookings"),
+            PARENT,
+            LIMIT,
+        ],
+        help: "Add a category, optionally as a subcategory of another one",
+        run: add_category,
+    },
+    Command {
+        name: "edit-expense",
+        aliases: &["ee"],
+        args: &[
+            ROW_ID,
+            NEW_NAME,
+            NEW_AMOUNT,
+            NEW_CATEGORY,
+            NEW_DATE,
+            NEW_CURRENCY,
+        ],
+        help: "Change fields of a booking",
+        run: edit_expense,
+    },
+    Command {
+        name: "edit-recurring",
+        aliases: &["er"],
+        args: &[
+            ROW_ID,
+            NEW_NAME,
+            NEW_AMOUNT,
+            NEW_CATEGORY,
+            NEW_CURRENCY,
+            PRESET,
+            EVERY,
+            UNTIL,
+            COUNT,
+            START,
+        ],
+        help: "Change fields or the recurrence of a recurring item",
+        run: edit_recurring,
+    },
+    Command {
+        name: "edit-category",
+        aliases: &["ec"],
+        args: &[
+            ROW_ID,
+            NEW_NAME,
+            Arg::named("token", "new token"),
+            PARENT,
+            LIMIT,
+        ],
+        help: "Change name, token, parent or limit of a category",
+        run: edit_category,
+    },
+    Command {
+        name: "delete-expense",
+        aliases: &["de"],
+        args: &[ROW_ID],
+        help: "Delete a booking",
+        run: delete_expense,
+    },
+    Command {
+        name: "delete-recurring",
+        aliases: &["dr"],
+        args: &[ROW_ID],
+        help: "Delete a recurring item, its past bookings stay",
+        run: delete_recurring,
+    },
+    Command {
+        name: "delete-category",
+        aliases: &["dc"],
+        args: &[
+            ROW_ID,
+            Arg::named("to", "category token that takes over its bookings"),
+        ],
+        help: "Delete a category, moving its bookings into another one",
+        run: delete_category,
+    },
+    Command {
+        name: "exchange-rate",
+        aliases: &["ax"],
+        args: &[
+            Arg::required("currency", "currency code like USD"),
+            Arg::required("rate", "value of one unit in the base currency"),
+        ],
+        help: "Set the exchange rate of a currency",
+        run: set_exchange_rate,
+    },
+    Command {
+        name: "assign",
+        aliases: &["as"],
+        args: &[
+            CATEGORY,
+            Arg::required("amount", "amount in the envelope for the period, 0 to remove it"),
+            Arg::optional(
+                "period",
+                "next, previous or a day of the period like 2026-11-01, defaults to the current one",
+            ),
+        ],
+        help: "Put money into the envelope of a category, in envelope mode",
+        run: assign,
+    },
+    Command {
+        name: "set",
+        aliases: &[],
+        args: &[
+            Arg::required("key", "name of the setting like Base_Currency"),
+            Arg::required("value", "new value, quoted if it contains spaces"),
+        ],
+        help: "Change a setting",
+        run: set_setting,
+    },
+    Command {
+        name: "archive",
+        aliases: &[],
+        args: &[],
+        help: "Move bookings of closed periods into the archive",
+        run: archive,
+    },
+    Command {
+        name: "help",
+        aliases: &["h", "?"],
+        args: &[Arg::optional("command", "name or alias of a command")],
+        help: "Show all commands or the arguments of one",
+        run: help,
+    },
+];
+
+/// Runs a command line like `ae "Coffee beans" f 3.50` or `edit-expense 4 amount=4.20` and
+/// returns text to show the user, like the help page.
+///
+/// Arguments are separated by spaces, quotes keep spaces in a name. Arguments listed in the usage
+/// can also be given by name, e.g. `ae amount=3.50 name=Coffee category=f`.
+pub fn handle_command(cmd: &str, storage: &dyn Storage) -> Result<Option<String>> {
+    let (command, args) = parser::parse(cmd)?;
+    (command.run)(args, storage)
+}
+
+fn add_expense(args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    add_booking(BookingType::Expense, args, storage)
+}
+
+fn add_income(args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    add_booking(BookingType::Income, args, storage)
+}
+
+fn add_booking(kind: BookingType, mut args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    let (name, token, amount) = parse_item(&mut args, storage)?;
+    let today = Local::now().date_naive();
+    let (currency, date) = parse_booking_options(args, today, storage)?;
+    let mut booking = BudgetBooking::new(&name, kind, &token, amount).in_currency(&currency);
+    if let Some(date) = date.filter(|d| *d != today) {
+        booking = booking.on_date(date);
+    }
+    journal::apply(Change::AddBooking(booking), storage)?;
+    Ok(None)
+}
+
+fn add_recurring_expense(args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    add_recurring(BookingType::Expense, args, storage)
+}
+
+fn add_recurring_income(args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    add_recurring(BookingType::Income, args, storage)
+}
+
+fn add_recurring(
+    kind: BookingType,
+    mut args: Args,
+    storage: &dyn Storage,
+) -> Result<Option<String>> {
+    let (name, token, amount) = parse_item(&mut args, storage)?;
+    let start = args.named("start").map(|s| parse_start(&s)).transpose()?;
+    let mut recurrence = Recurrence::monthly();
+    let currency = parse_recurring_options(args, &mut recurrence, storage)?;
+    let mut rec =
+        RecurringBooking::new(&name, kind, &token, amount, recurrence).in_currency(&currency);
+    if start.is_some() {
+        rec.anchor_date = start;
+        rec.next_due = next_due_from_today(&rec);
+    }
+    journal::apply(Change::AddRecurring(rec), storage)?;
+    Ok(None)
+}
+
+fn add_category(mut args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    let name = args.required("name")?;
+    let token = args.required("token")?;
+    let parent = args.named("parent");
+    let limit = args.named("limit");
+    args.finish()?;
+    let mut c = Category::new(&name, &parse_new_token(&token, None, storage)?);
+    if let Some(parent) = parent {
+        c.parent = parse_parent(&parent, &[], storage)?;
+    }
+    if let Some(limit) = limit {
+        c.limit = parse_limit(&limit)?;
+    }
+    journal::apply(Change::AddCategory(c), storage)?;
+    Ok(None)
+}
+
+fn delete_expense(args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    let booking = find(all_bookings(storage)?, parse_id(args)?, "booking")?;
+    journal::apply(Change::DeleteBooking(booking), storage)?;
+    Ok(None)
+}
+
+fn delete_recurring(args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    let rec = find(storage.get_recurring()?, parse_id(args)?, "recurring item")?;
+    journal::apply(Change::DeleteRecurring(rec), storage)?;
+    Ok(None)
+}
+
+fn delete_category(mut args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    let id = args.required_parsed("id", ID)?;
+    let target = args.named("to");
+    args.finish()?;
+    let categories = storage.get_categories()?;
+    let cat = find(categories.clone(), id, "category")?;
+
+    // Another category with the same token keeps the references valid
+    let shared = categories
+        .iter()
+        .any(|c| c.id != cat.id && c.token == cat.token);
+    let mut changes = vec![];
+    if !shared {
+        let (bookings, recurring) = category_usage(&cat.token, storage)?;
+        if !bookings.is_empty() || !recurring.is_empty() {
+            let to = target.ok_or_else(|| {
+                anyhow!(
+                    "Category '{}' is used by {} booking(s) and {} recurring item(s), \
+                     move them with: dc {} to=<token>",
+                    cat.token,
+                    bookings.len(),
+                    recurring.len(),
+                    cat.id
+                )
+            })?;
+            if to == cat.token {
+                return Err(anyhow!(
+                    "Move the bookings to another category than '{}'",
+                    to
+                ));
+            }
+            changes = reassign(&cat.token, &parse_known_category(&to, storage)?, storage)?;
+        } else {
+            // Money in its envelopes goes back to the unassigned money
+            for assignment in storage.get_assignments()? {
+                if assignment.category_token == cat.token {
+                    changes.push(unassign(&assignment));
+                }
+            }
+        }
+        // Subcategories move up a level
+        changes.extend(reparent(&cat.token, cat.parent.as_deref(), &categories));
+    }
+    changes.push(Change::DeleteCategory(cat));
+    journal::apply(Change::batch(changes), storage)?;
+    Ok(None)
+}
+
+fn set_exchange_rate(mut args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    const RATE: &str = "a rate greater than 0";
+    let currency = parse_currency(&args.required("currency")?)?;
+    let rate = args.required_parsed::<f64>("rate", RATE)?;
+    if !rate.is_finite() || rate <= 0.0 {
+        return Err(ParseError::invalid("rate", &rate.to_string(), RATE).into());
+    }
+    args.finish()?;
+    let before = storage
+        .get_exchange_rates()?
+        .into_iter()
+        .find(|r| r.currency == currency);
+    let after = Some(ExchangeRate::new(&currency, rate));
+    journal::apply(
+        Change::SetExchangeRate {
+            currency,
+            before,
+            after,
+        },
+        storage,
+    )?;
+    Ok(None)
+}
+
+fn set_setting(mut args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    let key = args.required("key")?;
+    let value = args.required("value")?;
+    args.finish()?;
+    let value = match key.as_str() {
+        "Base_Currency" => parse_currency(&value)?,
+        "Currency_Symbol" => value,
+        "Period_Start_Day" => match value.parse::<u32>() {
+            Ok(day) if (1..=31).contains(&day) => day.to_string(),
+            _ => return Err(ParseError::invalid("value", &value, "a day from 1 to 31").into()),
+        },
+        "Envelope_Mode" | "Archive_Split_Yearly" => match value.as_str() {
+            "true" | "false" => value,
+            _ => return Err(ParseError::invalid("value", &value, "true or false").into()),
+        },
+        _ => {
+            return Err(ParseError::invalid(
+                "key",
+                &key,
+                "Base_Currency, Currency_Symbol, Period_Start_Day, Envelope_Mode or \
+                 Archive_Split_Yearly",
+            )
+            .into())
+        }
+    };
+    storage.set_setting(Setting { key, value })?;
+    Ok(None)
+}
+
+fn assign(mut args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    let token = args.required("category")?;
+    let amount: Money = args.required_parsed("amount", AMOUNT)?;
+    if amount < Money::ZERO {
+        return Err(
+            ParseError::invalid("amount", &amount.to_string(), "an amount of 0 or more").into(),
+        );
+    }
+    let period = args.optional("period");
+    args.finish()?;
+    if !storage.get_setting_envelope_mode()? {
+        return Err(anyhow!(
+            "Envelope mode is off, turn it on with `set Envelope_Mode true`"
+        ));
+    }
+    let token = parse_known_category(&token, storage)?;
+    let start_day = storage.get_setting_period_start_day()?;
+    let period = match period {
+        Some(value) => parse_period(&value, start_day)?,
+        None => Period::current(start_day),
+    };
+
+    let before = storage
+        .get_assignments()?
+        .into_iter()
+        .find(|a| a.period == period.start && a.category_token == token);
+    let after = (amount != Money::ZERO).then(|| Assignment {
+        period: period.start,
+        category_token: token.clone(),
+        amount,
+    });
+    journal::apply(
+        Change::SetAssignment {
+            period: period.start,
+            category_token: token,
+            before,
+            after,
+        },
+        storage,
+    )?;
+    Ok(None)
+}
+
+fn archive(args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    args.finish()?;
+    archive::rollover(storage)?;
+    Ok(None)
+}
+
+fn help(mut args: Args, _storage: &dyn Storage) -> Result<Option<String>> {
+    let topic = args.take_positional();
+    let page = match topic.as_slice() {
+        [] => registry::overview(),
+        [word] => registry::find(word)
+            .ok_or_else(|| ParseError::UnknownCommand(word.to_string()))?
+            .help_page(),
+        [_, extra, ..] => return Err(ParseError::UnexpectedArgument(extra.to_string()).into()),
+    };
+    Ok(Some(page))
+}
+
+/// Name, category and amount of the add commands.
+fn parse_item(args: &mut Args, storage: &dyn Storage) -> Result<(String, String, Money)> {
+    let name = args.required("name")?;
+    let token = args.required("category")?;
+    let amount = parse_amount(&args.required("amount")?)?;
+    Ok((name, parse_known_category(&token, storage)?, amount))
+}
+
+/// The currency and date following a booking, in any order.
+fn parse_booking_options(
+    mut args: Args,
+    today: NaiveDate,
+    storage: &dyn Storage,
+) -> Result<(String, Option<NaiveDate>)> {
+    let mut currency = String::new();
+    let mut date = None;
+    for (key, value) in args.take_named() {
+        match key.as_str() {
+            "currency" => currency = parse_known_currency(&value, storage)?,
+            "date" => date = Some(parse_date(&value, today)?),
+            _ => return Err(ParseError::UnknownOption(key).into()),
+        }
+    }
+    for word in args.take_positional() {
+        match parse_date(&word, today) {
+            Ok(d) => date = Some(d),
+            Err(e) if word.starts_with(|c: char| c.is_ascii_digit() || c == '-') => {
+                return Err(e.into())
+            }
+            Err(_) => currency = parse_known_currency(&word, storage)?,
+        }
+    }
+    Ok((currency, date))
+}
+
+/// The recurrence options and currency following a recurring item.
+fn parse_recurring_options(
+    mut args: Args,
+    recurrence: &mut Recurrence,
+    storage: &dyn Storage,
+) -> Result<String> {
+    let mut currency = String::new();
+    for (key, value) in args.take_named() {
+        if parse_recurrence_option(&key, &value, recurrence)? {
+            continue;
+        }
+        if key != "currency" {
+            return Err(ParseError::UnknownOption(key).into());
+        }
+        currency = parse_known_currency(&value, storage)?;
+    }
+    for word in args.take_positional() {
+        if !recurrence_preset(&word, recurrence) {
+            currency = parse_known_currency(&word, storage)?;
+        }
+    }
+    Ok(currency)
+}
+
+/// A day up to `today`: `2026-10-14`, `today`, `yesterday`, `-3d` or the last `mon`..`sun`
+/// (today included).
+fn parse_date(value: &str, today: NaiveDate) -> Result<NaiveDate, ParseError> {
+    let err = || ParseError::invalid("date", value, DATE);
+    let lower = value.to_lowercase();
+    let date = match lower.as_str() {
+        "today" => today,
+        "yesterday" => today.pred_opt().ok_or_else(err)?,
+        _ => {
+            if let Some(days) = lower.strip_prefix('-').and_then(|d| d.strip_suffix('d')) {
+                let days = days.parse().map_err(|_| err())?;
+                today.checked_sub_days(Days::new(days)).ok_or_else(err)?
+            } else if let Ok(weekday) = lower.parse::<Weekday>() {
+                let back = (today.weekday().num_days_from_monday() + 7
+                    - weekday.num_days_from_monday())
+                    % 7;
+                today
+                    .checked_sub_days(Days::new(back.into()))
+                    .ok_or_else(err)?
+            } else {
+                value.parse().map_err(|_| err())?
+            }
+        }
+    };
+    if date > today {
+        return Err(ParseError::invalid("date", value, "a date not after today"));
+    }
+    Ok(date)
+}
+
+/// The budget period `next` or `previous` to the current one, or the one containing a date.
+fn parse_period(value: &str, start_day: u32) -> Result<Period, ParseError> {
+    let current = Period::current(start_day);
+    match value.to_lowercase().as_str() {
+        "next" => Ok(current.next()),
+        "previous" => Ok(current.previous()),
+        _ => value
+            .parse()
+            .map(|date| Period::containing(date, start_day))
+            .map_err(|_| {
+                ParseError::invalid("period", value, "next, previous or a date like 2026-11-01")
+            }),
+    }
+}
+
+fn parse_id(mut args: Args) -> Result<u64, ParseError> {
+    let id = args.required_parsed("id", ID)?;
+    args.finish()?;
+    Ok(id)
+}
+
+/// Active and archived bookings.
+fn all_bookings(storage: &dyn Storage) -> Result<Vec<BudgetBooking>> {
+    let mut bookings = storage.get_expenses()?;
+    bookings.extend(storage.get_expenses_archive()?);
+    Ok(bookings)
+}
+
+fn find<T: Record>(items: Vec<T>, id: u64, entity: &str) -> Result<T> {
+    items
+        .into_iter()
+        .find(|i| i.id() == id)
+        .ok_or_else(|| anyhow!("No {} with id {}", entity, id))
+}
+
+fn edit_expense(mut args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    let before = find(
+        all_bookings(storage)?,
+        args.required_parsed("id", ID)?,
+        "booking",
+    )?;
+    let mut booking = before.clone();
+    for (key, value) in args.take_named() {
+        match key.as_str() {
+            "name" => booking.name = value,
+            "amount" => booking.amount = parse_amount(&value)?,
+            "category" => booking.category_token = parse_known_category(&value, storage)?,
+            "currency" if value.is_empty() => booking.currency = String::new(),
+            "currency" => booking.currency = parse_known_currency(&value, storage)?,
+            "date" => {
+                let date = parse_date(&value, Local::now().date_naive())?;
+                if date != booking.local_date() {
+                    booking = booking.on_date(date);
+                }
+            }
+            _ => return Err(ParseError::UnknownOption(key).into()),
+        }
+    }
+    args.finish()?;
+    journal::apply(
+        Change::UpdateBooking {
+            before,
+            after: booking,
+        },
+        storage,
+    )?;
+    Ok(None)
+}
+
+fn edit_recurring(mut args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    let id = args.required_parsed("id", ID)?;
+    let before = find(storage.get_recurring()?, id, "recurring item")?;
+    let mut rec = before.clone();
+    for (key, value) in args.take_named() {
+        if parse_recurrence_option(&key, &value, &mut rec.recurrence)? {
+            continue;
+        }
+        match key.as_str() {
+            "name" => rec.name = value,
+            "start" => rec.anchor_date = Some(parse_start(&value)?),
+            "amount" => rec.amount = parse_amount(&value)?,
+            "category" => rec.category_token = parse_known_category(&value, storage)?,
+            "currency" if value.is_empty() => rec.currency = String::new(),
+            "currency" => rec.currency = parse_known_currency(&value, storage)?,
+            _ => return Err(ParseError::UnknownOption(key).into()),
+        }
+    }
+    for word in args.take_positional() {
+        if !recurrence_preset(&word, &mut rec.recurrence) {
+            return Err(ParseError::UnexpectedArgument(word).into());
+        }
+    }
+    if rec.recurrence != before.recurrence || rec.anchor_date != before.anchor_date {
+        rec.next_due = next_due_from_today(&rec);
+    }
+    journal::apply(Change::UpdateRecurring { before, after: rec }, storage)?;
+    Ok(None)
+}
+
+fn edit_category(mut args: Args, storage: &dyn Storage) -> Result<Option<String>> {
+    let id = args.required_parsed("id", ID)?;
+    let before = find(storage.get_categories()?, id, "category")?;
+    let mut cat = before.clone();
+    for (key, value) in args.take_named() {
+        match key.as_str() {
+            "name" => cat.name = value,
+            "token" => cat.token = parse_new_token(&value, Some(id), storage)?,
+            "parent" => cat.parent = Some(value),
+            "limit" => cat.limit = parse_limit(&value)?,
+            _ => return Err(ParseError::UnknownOption(key).into()),
+        }
+    }
+    args.finish()?;
+    if let Some(parent) = cat.parent.take() {
+        cat.parent = parse_parent(&parent, &[&before.token, &cat.token], storage)?;
+    }
+
+    // Bookings, recurring items and subcategories follow a renamed token
+    let mut changes = vec![];
+    if cat.token != before.token {
+        changes = reassign(&before.token, &cat.token, storage)?;
+        let categories = storage.get_categories()?;
+        changes.extend(reparent(&before.token, Some(&cat.token), &categories));
+    }
+    changes.push(Change::UpdateCategory { before, after: cat });
+    journal::apply(Change::batch(changes), storage)?;
+    Ok(None)
+}
+
+/// The positive amount of a booking or recurring item, its kind tells which way the money goes.
+fn parse_amount(value: &str) -> Result<Money, ParseError> {
+    match value.parse() {
+        Ok(amount) if amount > Money::ZERO => Ok(amount),
+        Ok(_) => Err(ParseError::invalid(
+            "amount",
+            value,
+            "an amount greater than 0",
+        )),
+        Err(_) => Err(ParseError::invalid("amount", value, AMOUNT)),
+    }
+}
+
+/// A positive amount, or `None` for an empty value.
+fn parse_limit(value: &str) -> Result<Option<Money>, ParseError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    match value.parse() {
+        Ok(limit) if limit > Money::ZERO => Ok(Some(limit)),
+        _ => Err(ParseError::invalid(
+            "limit",
+            value,
+            "an amount greater than 0",
+        )),
+    }
+}
+
+/// Applies a preset like `weekly` or `quarterly`, returns false for other words.
+fn recurrence_preset(word: &str, recurrence: &mut Recurrence) -> bool {
+    let (rate_type, interval) = match word {
+        "weekly" => (RecurringType::Weekly, 1),
+        "biweekly" => (RecurringType::Weekly, 2),
+        "monthly" => (RecurringType::Monthly, 1),
+        "quarterly" => (RecurringType::Monthly, 3),
+        "semiannual" => (RecurringType::Monthly, 6),
+        "yearly" => (RecurringType::Yearly, 1),
+        _ => return false,
+    };
+    recurrence.rate_type = rate_type;
+    recurrence.interval = interval;
+    true
+}
+
+/// Applies a recurrence option like `every=4w`, `until=2027-06-30` or `count=12`.
+///
+/// Returns false if the option is not about the recurrence.
+fn parse_recurrence_option(
+    key: &str,
+    value: &str,
+    recurrence: &mut Recurrence,
+) -> Result<bool, ParseError> {
+    match key {
+        "every" => {
+            let err = || ParseError::invalid("every", value, "an interval like 4w, 3m or 2y");
+            let (count, unit) = value.split_at(value.len().saturating_sub(1));
+            let interval = count.parse::<u32>().map_err(|_| err())?;
+            if interval == 0 {
+                return Err(err());
+            }
+            recurrence.rate_type = match unit {
+                "w" => RecurringType::Weekly,
+                "m" => RecurringType::Monthly,
+                "y" => RecurringType::Yearly,
+                _ => return Err(err()),
+            };
+            recurrence.interval = interval;
+        }
+        // An empty value removes the end
+        "until" | "count" if value.is_empty() => {
+            if key == "until" {
+                recurrence.until = None;
+            } else {
+                recurrence.count = None;
+            }
+        }
+        "until" => {
+            recurrence.until = Some(
+                value
+                    .parse()
+                    .map_err(|_| ParseError::invalid("until", value, "a date like 2027-06-30"))?,
+            );
+        }
+        "count" => {
+            let count = value
+                .parse::<u32>()
+                .ok()
+                .filter(|c| *c > 0)
+                .ok_or_else(|| ParseError::invalid("count", value, "a number of occurrences"))?;
+            recurrence.count = Some(count);
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// The day a recurring item's occurrences are counted from, in the past or the future.
+fn parse_start(value: &str) -> Result<NaiveDate, ParseError> {
+    value
+        .parse()
+        .map_err(|_| ParseError::invalid("start", value, "a date like 2026-11-01"))
+}
+
+/// The first occurrence from today on. Earlier ones are already booked, or were before the item
+/// was added or changed.
+fn next_due_from_today(rec: &RecurringBooking) -> Option<NaiveDate> {
+    let today = Local::now().date_naive();
+    today.pred_opt().and_then(|d| rec.occurrence_after(d))
+}
+
+/// The options `recurrence_preset` and `parse_recurrence_option` read back into `recurrence`,
+/// e.g. `every=3m count=4`.
+pub fn recurrence_options(recurrence: &Recurrence) -> String {
+    let mut options = vec![match (&recurrence.rate_type, recurrence.interval) {
+        (RecurringType::Weekly, 1) => "weekly".to_string(),
+        (RecurringType::Monthly, 1) => "monthly".to_string(),
+        (RecurringType::Yearly, 1) => "yearly".to_string(),
+        (RecurringType::Weekly, n) => format!("every={}w", n),
+        (RecurringType::Monthly, n) => format!("every={}m", n),
+        (RecurringType::Yearly, n) => format!("every={}y", n),
+    }];
+    if let Some(until) = recurrence.until {
+        options.push(format!("until={}", until));
+    }
+    if let Some(count) = recurrence.count {
+        options.push(format!("count={}", count));
+    }
+    options.join(" ")
+}
+
+fn parse_currency(code: &str) -> Result<String, ParseError> {
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(ParseError::invalid(
+            "currency",
+            code,
+            "a currency code like EUR",
+        ));
+    }
+    Ok(code.to_ascii_uppercase())
+}
+
+/// Bookings and recurring items may only use the tokens of existing categories.
+fn parse_known_category(token: &str, storage: &dyn Storage) -> Result<String> {
+    if !storage.get_categories()?.iter().any(|c| c.token == token) {
+        return Err(anyhow!(
+            "Unknown category '{}', add it with: ac <Name> {}",
+            token,
+            token
+        ));
+    }
+    Ok(token.to_string())
+}
+
+/// A token not used by any category but the one with id `own`.
+fn parse_new_token(token: &str, own: Option<u64>, storage: &dyn Storage) -> Result<String> {
+    if token.is_empty() || token.contains(char::is_whitespace) {
+        return Err(ParseError::invalid("token", token, "a short token without spaces").into());
+    }
+    let taken = storage
+        .get_categories()?
+        .iter()
+        .any(|c| c.token == token && Some(c.id) != own);
+    if taken {
+        return Err(anyhow!("A category with token '{}' already exists", token));
+    }
+    Ok(token.to_string())
+}
+
+/// Active and archived bookings and recurring items using the category `token`.
+pub fn category_usage(
+    token: &str,
+    storage: &dyn Storage,
+) -> Result<(Vec<BudgetBooking>, Vec<RecurringBooking>)> {
+    let bookings = all_bookings(storage)?
+        .into_iter()
+        .filter(|b| b.category_token == token)
+        .collect();
+    let recurring = storage
+        .get_recurring()?
+        .into_iter()
+        .filter(|r| r.category_token == token)
+        .collect();
+    Ok((bookings, recurring))
+}
+
+/// Changes moving everything in category `from` into category `to`.
+fn reassign(from: &str, to: &str, storage: &dyn Storage) -> Result<Vec<Change>> {
+    let (bookings, recurring) = category_usage(from, storage)?;
+    let mut changes = vec![];
+    for before in bookings {
+        let mut after = before.clone();
+        after.category_token = to.to_string();
+        changes.push(Change::UpdateBooking { before, after });
+    }
+    for before in recurring {
+        let mut after = before.clone();
+        after.category_token = to.to_string();
+        changes.push(Change::UpdateRecurring { before, after });
+    }
+    // Envelopes are merged with the ones of `to` in the same period
+    let assignments = storage.get_assignments()?;
+    for moved in assignments.iter().filter(|a| a.category_token == from) {
+        let existing = assignments
+            .iter()
+            .find(|a| a.period == moved.period && a.category_token == to);
+        let mut after = moved.clone();
+        after.category_token = to.to_string();
+        if let Some(existing) = existing {
+            after.amount += existing.amount;
+        }
+        changes.push(unassign(moved));
+        changes.push(Change::SetAssignment {
+            period: moved.period,
+            category_token: to.to_string(),
+            before: existing.cloned(),
+            after: Some(after),
+        });
+    }
+    Ok(changes)
+}
+
+fn unassign(assignment: &Assignment) -> Change {
+    Change::SetAssignment {
+        period: assignment.period,
+        category_token: assignment.category_token.clone(),
+        before: Some(assignment.clone()),
+        after: None,
+    }
+}
+
+/// Changes moving the subcategories of `from` below `to`, or to the top level.
+fn reparent(from: &str, to: Option<&str>, categories: &[Category]) -> Vec<Change> {
+    categories
+        .iter()
+        .filter(|c| c.parent.as_deref() == Some(from))
+        .map(|before| {
+            let mut after = before.clone();
+            after.parent = to.map(|t| t.to_string());
+            Change::UpdateCategory {
+                before: before.clone(),
+                after,
+            }
+        })
+        .collect()
+}
+
+/// An existing category token, or `None` for an empty value. The parent may not be one of the
+/// `own` tokens of the category or below it, so the categories stay a tree.
+fn parse_parent(value: &str, own: &[&str], storage: &dyn Storage) -> Result<Option<String>> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let parent = parse_known_category(value, storage)?;
+    let categories = storage.get_categories()?;
+    if category_path(&categories, &parent)
+        .iter()
+        .any(|t| own.contains(t))
+    {
+        return Err(anyhow!(
+            "Category '{}' can't be its own parent or below itself",
+            own[0]
+        ));
+    }
+    Ok(Some(parent))
+}
+
+/// Only currencies that can be converted into the base currency are accepted for bookings.
+fn parse_known_currency(code: &str, storage: &dyn Storage) -> Result<String> {
+    let code = parse_currency(code)?;
+    let has_rate = storage
+        .get_exchange_rates()?
+        .iter()
+        .any(|r| r.currency == code);
+    if !has_rate && storage.get_setting_base_currency()? != code {
+        return Err(anyhow!(
+            "No exchange rate for {}, add one with: ax {} <Rate>",
+            code,
+            code
+        ));
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryStorage;
+
+    #[test]
+    fn test_add_commands() {
+        let storage = MemoryStorage::default();
+        handle_command("ac Food f", &storage).unwrap();
+        handle_command("ae Coffee f 3.50", &storage).unwrap();
+        handle_command("are Insurance f 120 yearly", &storage).unwrap();
+        handle_command("ari Salary f 2000", &storage).unwrap();
+        handle_command("ai Refund f 12 yesterday", &storage).unwrap();
+
+        let categories = storage.get_categories().unwrap();
+        assert_eq!(categories.len(), 1);
+        assert_eq!(categories[0].token, "f");
+
+        let bookings = all_bookings(&storage).unwrap();
+        assert_eq!(bookings.len(), 2);
+        assert_eq!(bookings[0].kind, BookingType::Expense);
+        assert_eq!(bookings[0].amount, Money::from_minor(350));
+        assert_eq!(bookings[1].kind, BookingType::Income);
+        assert_eq!(bookings[1].amount, Money::from_minor(1200));
+
+        let recurring = storage.get_recurring().unwrap();
+        assert_eq!(recurring.len(), 2);
+        assert_eq!(
+            recurring[0].recurrence,
+            Recurrence::new(RecurringType::Yearly, 1)
+        );
+        assert_eq!(recurring[1].kind, BookingType::Income);
+        assert_eq!(recurring[1].recurrence, Recurrence::monthly());
+    }
+
+    #[test]
+    fn test_recurrence_options() {
+        let storage = MemoryStorage::default();
+        handle_command("ac Food f", &storage).unwrap();
+        handle_command("are Insurance f 90 quarterly", &storage).unwrap();
+        handle_command("are Gym f 30 every=4w until=2027-06-30", &storage).unwrap();
+        handle_command("ari Salary f 1500 biweekly count=26", &storage).unwrap();
+        assert!(handle_command("are Gym f 30 every=0w", &storage).is_err());
+        assert!(handle_command("are Gym f 30 every=4d", &storage).is_err());
+        assert!(handle_command("are Gym f 30 until=tomorrow", &storage).is_err());
+        assert!(handle_command("are Gym f 30 count=0", &storage).is_err());
+
+        let recurring = storage.get_recurring().unwrap();
+        assert_eq!(recurring.len(), 3);
+        assert_eq!(
+            recurring[0].recurrence,
+            Recurrence::new(RecurringType::Monthly, 3)
+        );
+        assert_eq!(recurring[1].recurrence.rate_type, RecurringType::Weekly);
+        assert_eq!(recurring[1].recurrence.interval, 4);
+        assert_eq!(
+            recurring[1].recurrence.until.unwrap().to_string(),
+            "2027-06-30"
+        );
+        assert_eq!(recurring[2].recurrence.interval, 2);
+        assert_eq!(recurring[2].recurrence.count, Some(26));
+
+        for rec in recurring {
+            let mut parsed = Recurrence::monthly();
+            for option in recurrence_options(&rec.recurrence).split(' ') {
+                let parsed_option = match option.split_once('=') {
+                    Some((key, value)) => parse_recurrence_option(key, value, &mut parsed).unwrap(),
+                    None => recurrence_preset(option, &mut parsed),
+                };
+                assert!(parsed_option);
+            }
+            assert_eq!(parsed, rec.recurrence);
+        }
+
+        handle_command("er 2 until=", &storage).unwrap();
+        handle_command("er 3 count=", &storage).unwrap();
+        let recurring = storage.get_recurring().unwrap();
+        assert_eq!(recurring[1].recurrence.until, None);
+        assert_eq!(recurring[2].recurrence.count, None);
+    }
+
+    #[test]
+    fn test_currency_commands() {
+        let storage = MemoryStorage::default();
+        handle_command("ac Food f", &storage).unwrap();
+        assert!(handle_command("ae Lunch f 12.50 usd", &storage).is_err());
+        assert!(handle_command("ax USD 0", &storage).is_err());
+        assert!(handle_command("ax US 1.1", &storage).is_err());
+
+        handle_command("ax usd 0.92", &storage).unwrap();
+        handle_command("ax USD 0.93", &storage).unwrap();
+        handle_command("ae Lunch f 12.50 usd", &storage).unwrap();
+        handle_command("are Cloud f 120 yearly USD", &storage).unwrap();
+
+        let rates = storage.get_exchange_rates().unwrap();
+        assert_eq!(rates.len(), 1);
+        assert_eq!(rates[0].rate, 0.93);
+        assert_eq!(storage.get_expenses().unwrap()[0].currency, "USD");
+        let recurring = storage.get_recurring().unwrap();
+        assert_eq!(recurring[0].currency, "USD");
+        assert_eq!(
+            recurring[0].recurrence,
+            Recurrence::new(RecurringType::Yearly, 1)
+        );
+    }
+
+    #[test]
+    fn test_set_command() {
+        let storage = MemoryStorage::default();
+        handle_command("set Period_Start_Day 25", &storage).unwrap();
+        handle_command("set Base_Currency chf", &storage).unwrap();
+        handle_command("set Currency_Symbol \"Fr. \"", &storage).unwrap();
+        handle_command("set Base_Currency EUR", &storage).unwrap();
+        assert_eq!(storage.get_setting_period_start_day().unwrap(), 25);
+        assert_eq!(storage.get_setting_base_currency().unwrap(), "EUR");
+        assert_eq!(storage.get_setting_currency_symbol().unwrap(), "Fr. ");
+        assert_eq!(storage.get_settings().unwrap().len(), 3);
+
+        let error = |cmd: &str| handle_command(cmd, &storage).unwrap_err().to_string();
+        assert_eq!(
+            error("set Period_Start_Day 32"),
+            "Invalid value '32', expected a day from 1 to 31"
+        );
+        assert_eq!(
+            error("set Envelope_Mode yes"),
+            "Invalid value 'yes', expected true or false"
+        );
+        assert!(error("set Colour red").starts_with("Invalid key 'Colour'"));
+        assert!(!storage.get_setting_envelope_mode().unwrap());
+    }
+
+    #[test]
+    fn test_edit_and_delete_commands() {
+        let storage = MemoryStorage::default();
+        handle_command("ac Food f", &storage).unwrap();
+        handle_command("ae Coffee f 3.50", &storage).unwrap();
+        handle_command("ae Lunch f 9.00", &storage).unwrap();
+        handle_command("are Gym f 30", &storage).unwrap();
+
+        handle_command("ee 2 amount=12.50 name=Dinner", &storage).unwrap();
+        let lunch = &storage.get_expenses().unwrap()[1];
+        assert_eq!((lunch.id, lunch.name.as_str()), (2, "Dinner"));
+        assert_eq!(lunch.amount, Money::from_minor(1250));
+
+        handle_command("er 1 amount=40 every=4w", &storage).unwrap();
+        let gym = &storage.get_recurring().unwrap()[0];
+        assert_eq!(gym.amount, Money::from_minor(4000));
+        assert_eq!(gym.recurrence, Recurrence::new(RecurringType::Weekly, 4));
+
+        handle_command("ec 1 name=Groceries", &storage).unwrap();
+        assert_eq!(storage.get_categories().unwrap()[0].name, "Groceries");
+
+        handle_command("de 1", &storage).unwrap();
+        let bookings = storage.get_expenses().unwrap();
+        assert_eq!(bookings.len(), 1);
+        assert_eq!(bookings[0].name, "Dinner");
+        handle_command("dr 1", &storage).unwrap();
+        assert!(storage.get_recurring().unwrap().is_empty());
+
+        assert!(handle_command("de 1", &storage).is_err());
+        assert!(handle_command("de x", &storage).is_err());
+        assert!(handle_command("ee 2 colour=red", &storage).is_err());
+        assert!(handle_command("ee 2 12.50", &storage).is_err());
+    }
+
+    #[test]
+    fn test_quoted_and_named_arguments() {
+        let storage = MemoryStorage::default();
+        handle_command("ac Food f", &storage).unwrap();
+        handle_command(r#"ae "Coffee beans" f 3.50"#, &storage).unwrap();
+        handle_command("ae amount=2 category=f name='Tea'", &storage).unwrap();
+        handle_command(r#"ee 1 name="Espresso \"doppio\"""#, &storage).unwrap();
+        handle_command(r#"ac "Eating out" e"#, &storage).unwrap();
+
+        let bookings = storage.get_expenses().unwrap();
+        assert_eq!(bookings[0].name, r#"Espresso "doppio""#);
+        assert_eq!(bookings[1].name, "Tea");
+        assert_eq!(bookings[1].amount, Money::from_minor(200));
+        assert_eq!(storage.get_categories().unwrap()[1].name, "Eating out");
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let storage = MemoryStorage::default();
+        handle_command("ac Food f", &storage).unwrap();
+        let error = |cmd: &str| handle_command(cmd, &storage).unwrap_err().to_string();
+        assert_eq!(
+            error("ae Coffee f"),
+            "Missing <amount>, usage: add-expense <name> <category> <amount> [date] [currency]"
+        );
+        assert_eq!(
+            error("ae Coffee f 3,50"),
+            "Invalid amount '3,50', expected an amount like 12.50"
+        );
+        assert_eq!(
+            error("ae Coffee f 0"),
+            "Invalid amount '0', expected an amount greater than 0"
+        );
+        assert_eq!(
+            error("ai Refund f -3.50"),
+            "Invalid amount '-3.50', expected an amount greater than 0"
+        );
+        assert_eq!(
+            error("are Gym f -30"),
+            "Invalid amount '-30', expected an amount greater than 0"
+        );
+        handle_command("ae Coffee f 3.50", &storage).unwrap();
+        assert_eq!(
+            error("ee 1 amount=0"),
+            "Invalid amount '0', expected an amount greater than 0"
+        );
+        assert_eq!(error("ae \"Coffee f 3"), "Missing closing \" quote");
+        assert_eq!(error("ac Food f extra"), "Unexpected argument 'extra'");
+        assert_eq!(
+            error("de x"),
+            "Invalid id 'x', expected an id from the # column"
+        );
+        assert_eq!(
+            error("ax USD -1"),
+            "Invalid rate '-1', expected a rate greater than 0"
+        );
+        assert_eq!(
+            error("are Gym f 30 every=2d"),
+            "Invalid every '2d', expected an interval like 4w, 3m or 2y"
+        );
+        assert_eq!(error("ae Coffee f 3 colour=red"), "Unknown option 'colour'");
+        assert_eq!(error("xx"), "Unknown command 'xx'");
+        assert_eq!(error(""), "Empty command");
+    }
+
+    #[test]
+    fn test_booking_dates() {
+        let today = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap(); // a Sunday
+        let date = |value: &str| parse_date(value, today).map(|d| d.to_string());
+        assert_eq!(date("2026-10-14").unwrap(), "2026-10-14");
+        assert_eq!(date("yesterday").unwrap(), "2026-10-17");
+        assert_eq!(date("-3d").unwrap(), "2026-10-15");
+        assert_eq!(date("mon").unwrap(), "2026-10-12");
+        assert_eq!(date("Saturday").unwrap(), "2026-10-17");
+        assert_eq!(date("sun").unwrap(), "2026-10-18");
+        assert!(date("2026-10-19").is_err());
+        assert!(date("-xd").is_err());
+        assert!(date("someday").is_err());
+
+        let storage = MemoryStorage::default();
+        handle_command("ac Food f", &storage).unwrap();
+        handle_command("ax USD 0.9", &storage).unwrap();
+        handle_command("ae Lunch f 9 yesterday USD", &storage).unwrap();
+        handle_command("ae Dinner f 20 date=today", &storage).unwrap();
+        assert!(handle_command("ae Lunch f 9 2026-02-30", &storage).is_err());
+        assert!(handle_command("ae Lunch f 9 XYZ", &storage).is_err());
+
+        let today = Local::now().date_naive();
+        let bookings = all_bookings(&storage).unwrap();
+        let lunch = bookings.iter().find(|b| b.name == "Lunch").unwrap();
+        assert_eq!(lunch.local_date(), today.pred_opt().unwrap());
+        assert_eq!(lunch.currency, "USD");
+        let dinner = bookings.iter().find(|b| b.name == "Dinner").unwrap();
+        assert_eq!(dinner.local_date(), today);
+
+        handle_command(&format!("ee {} date=-2d", dinner.id), &storage).unwrap();
+        let bookings = all_bookings(&storage).unwrap();
+        let dinner = bookings.iter().find(|b| b.name == "Dinner").unwrap();
+        assert_eq!(dinner.local_date(), today - Days::new(2));
+    }
+
+    #[test]
+    fn test_subcategories() {
+        let storage = MemoryStorage::default();
+        let parent = |token: &str| {
+            storage
+                .get_categories()
+                .unwrap()
+                .into_iter()
+                .find(|c| c.token == token)
+                .and_then(|c| c.parent)
+        };
+        handle_command("ac Housing h", &storage).unwrap();
+        handle_command("ac Utilities u parent=h", &storage).unwrap();
+        handle_command("ac Power p parent=u", &storage).unwrap();
+        assert!(handle_command("ac Rent r parent=x", &storage).is_err());
+        assert_eq!(parent("p").as_deref(), Some("u"));
+
+        // no loops
+        let error = handle_command("ec 1 parent=p", &storage).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Category 'h' can't be its own parent or below itself"
+        );
+        assert!(handle_command("ec 2 parent=u", &storage).is_err());
+
+        // subcategories follow a renamed token and move up when their parent is deleted
+        handle_command("ec 2 token=ut", &storage).unwrap();
+        assert_eq!(parent("p").as_deref(), Some("ut"));
+        handle_command("dc 2", &storage).unwrap();
+        assert_eq!(parent("p").as_deref(), Some("h"));
+        journal::undo(&storage).unwrap();
+        assert_eq!(parent("p").as_deref(), Some("ut"));
+
+        handle_command("ec 3 parent=", &storage).unwrap();
+        assert_eq!(parent("p"), None);
+    }
+
+    #[test]
+    fn test_category_limits() {
+        let storage = MemoryStorage::default();
+        let limit = |id: usize| storage.get_categories().unwrap()[id].limit;
+        handle_command("ac Food f limit=250", &storage).unwrap();
+        handle_command("ac Rent r", &storage).unwrap();
+        assert_eq!(limit(0), Some("250".parse().unwrap()));
+        assert_eq!(limit(1), None);
+
+        handle_command("ec 2 limit=12.50", &storage).unwrap();
+        assert_eq!(limit(1), Some("12.50".parse().unwrap()));
+        handle_command("ec 1 limit=", &storage).unwrap();
+        assert_eq!(limit(0), None);
+        for invalid in ["ec 1 limit=0", "ec 1 limit=-5", "ac Fun u limit=lots"] {
+            let error = handle_command(invalid, &storage).unwrap_err().to_string();
+            assert!(
+                error.contains("expected an amount greater than 0"),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_assign() {
+        let storage = MemoryStorage::default();
+        handle_command("ac Food f", &storage).unwrap();
+        handle_command("ac Rent r", &storage).unwrap();
+        let error = handle_command("as f 100", &storage)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("`set Envelope_Mode true`"), "{}", error);
+        handle_command("set Envelope_Mode true", &storage).unwrap();
+
+        let amounts = || -> Vec<(String, String)> {
+            storage
+                .get_assignments()
+                .unwrap()
+                .into_iter()
+                .map(|a| (a.category_token, a.amount.to_string()))
+                .collect()
+        };
+        handle_command("as f 100", &storage).unwrap();
+        handle_command("assign r 800 next", &storage).unwrap();
+        handle_command("as f 120", &storage).unwrap();
+        assert_eq!(
+            amounts(),
+            vec![
+                ("f".to_string(), "120.00".to_string()),
+                ("r".to_string(), "800.00".to_string())
+            ]
+        );
+        let periods: Vec<NaiveDate> = storage
+            .get_assignments()
+            .unwrap()
+            .iter()
+            .map(|a| a.period)
+            .collect();
+        let current = Period::current(1);
+        assert_eq!(periods, vec![current.start, current.next().start]);
+
+        journal::undo(&storage).unwrap();
+        assert_eq!(amounts()[0].1, "100.00");
+        handle_command("as f 0", &storage).unwrap();
+        assert_eq!(amounts().len(), 1);
+        for invalid in ["as f -5", "as x 5", "as f 5 someday"] {
+            assert!(handle_command(invalid, &storage).is_err(), "{}", invalid);
+        }
+
+        // envelopes are merged into the category taking over, or emptied with their category
+        handle_command("as f 50 next", &storage).unwrap();
+        handle_command("ae Bread f 3", &storage).unwrap();
+        handle_command("dc 1 to=r", &storage).unwrap();
+        assert_eq!(amounts(), vec![("r".to_string(), "850.00".to_string())]);
+        handle_command("ac Fun u", &storage).unwrap();
+        handle_command("as u 20", &storage).unwrap();
+        handle_command("dc 3", &storage).unwrap();
+        assert_eq!(amounts().len(), 1);
+        journal::undo(&storage).unwrap();
+        assert_eq!(amounts().len(), 2);
+    }
+
+    #[test]
+    fn test_category_integrity() {
+        let storage = MemoryStorage::default();
+        handle_command("ac Food f", &storage).unwrap();
+        handle_command("ac Rent r", &storage).unwrap();
+        assert!(handle_command("ac Fuel f", &storage).is_err());
+        assert!(handle_command("ae Coffee x 3", &storage).is_err());
+        assert!(handle_command("are Gym x 30", &storage).is_err());
+        handle_command("ae Coffee f 3", &storage).unwrap();
+        handle_command("are Snacks f 20", &storage).unwrap();
+        assert!(handle_command("ee 1 category=x", &storage).is_err());
+        assert!(handle_command("ec 2 token=f", &storage).is_err());
+
+        // renaming a token carries its bookings along
+        handle_command("ec 1 token=g", &storage).unwrap();
+        assert_eq!(storage.get_expenses().unwrap()[0].category_token, "g");
+        assert_eq!(storage.get_recurring().unwrap()[0].category_token, "g");
+
+        let error = handle_command("dc 1", &storage).unwrap_err().to_string();
+        assert!(error.contains("dc 1 to=<token>"), "{}", error);
+        assert!(handle_command("dc 1 to=g", &storage).is_err());
+        assert!(handle_command("dc 1 to=x", &storage).is_err());
+        handle_command("dc 1 to=r", &storage).unwrap();
+        assert_eq!(storage.get_categories().unwrap().len(), 1);
+        assert_eq!(storage.get_expenses().unwrap()[0].category_token, "r");
+        assert_eq!(storage.get_recurring().unwrap()[0].category_token, "r");
+
+        // reassigning and deleting is undone in one step
+        journal::undo(&storage).unwrap();
+        assert_eq!(storage.get_categories().unwrap().len(), 2);
+        assert_eq!(storage.get_expenses().unwrap()[0].category_token, "g");
+        assert_eq!(storage.get_recurring().unwrap()[0].category_token, "g");
+    }
+
+    #[test]
+    fn test_long_names_and_help() {
+        let storage = MemoryStorage::default();
+        handle_command("add-category Food f", &storage).unwrap();
+        handle_command("add-expense Coffee f 3.50", &storage).unwrap();
+        handle_command("add-recurring-expense Gym f 30 weekly", &storage).unwrap();
+        handle_command("edit-expense 1 name=Tea", &storage).unwrap();
+        assert_eq!(storage.get_expenses().unwrap()[0].name, "Tea");
+        assert_eq!(storage.get_recurring().unwrap().len(), 1);
+
+        let page = handle_command("help ae", &storage).unwrap().unwrap();
+        assert!(page.starts_with("add-expense (ae): "));
+        assert!(page.contains("Usage: add-expense <name> <category> <amount> [date] [currency]"));
+        let overview = handle_command("help", &storage).unwrap().unwrap();
+        assert_eq!(overview.lines().count(), COMMANDS.len() + 2);
+        assert!(handle_command("help xx", &storage).is_err());
+
+        let mut names: Vec<&str> = COMMANDS
+            .iter()
+            .flat_map(|c| c.aliases.iter().copied().chain([c.name]))
+            .collect();
+        let count = names.len();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), count, "command names must be unique");
+    }
+
+    #[test]
+    fn test_invalid_command() {
+        let storage = MemoryStorage::default();
+        assert!(handle_command("xx foo", &storage).is_err());
+        assert!(handle_command("ae Coffee f 3,50", &storage).is_err());
+        assert!(storage.get_expenses().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+use std::{collections::VecDeque, str::FromStr};
+
+use thiserror::Error;
+
+use super::registry::{self, Command};
+
+/// Why a command line could not be turned into a command, shown in the overview bar.
+#[derive(Error, Debug, PartialEq)]
+pub enum ParseError {
+    #[error("Empty command")]
+    Empty,
+    #[error("Unknown command '{0}'")]
+    UnknownCommand(String),
+    #[error("Missing closing {0} quote")]
+    UnterminatedQuote(char),
+    #[error("Missing <{name}>, usage: {usage}")]
+    MissingArgument { name: &'static str, usage: String },
+    #[error("Invalid {name} '{value}', expected {expected}")]
+    InvalidArgument {
+        name: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+    #[error("Unexpected argument '{0}'")]
+    UnexpectedArgument(String),
+    #[error("Unknown option '{0}'")]
+    UnknownOption(String),
+}
+
+impl ParseError {
+    pub fn invalid(name: &'static str, value: &str, expected: &'static str) -> Self {
+        ParseError::InvalidArgument {
+            name,
+            value: value.to_string(),
+            expected,
+        }
+    }
+}
+
+/// A word of the command line. `key=value` words have a key unless the `=` was quoted.
+#[derive(Debug, PartialEq)]
+pub struct Token {
+    pub key: Option<String>,
+    pub value: String,
+}
+
+/// Splits a command line on whitespace. Single or double quotes keep spaces in a word, a
+/// backslash escapes the next character outside of single quotes.
+pub fn tokenize(line: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = line.chars();
+    let mut current: Option<Token> = None;
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            tokens.extend(current.take());
+            quoted = false;
+            continue;
+        }
+        let token = current.get_or_insert_with(|| Token {
+            key: None,
+            value: String::new(),
+        });
+        match c {
+            '\'' | '"' => {
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some('\\') if c == '"' => match chars.next() {
+                            Some(escaped) => token.value.push(escaped),
+                            None => return Err(ParseError::UnterminatedQuote(c)),
+                        },
+                        Some(other) => token.value.push(other),
+                        None => return Err(ParseError::UnterminatedQuote(c)),
+                    }
+                }
+            }
+            '\\' => token.value.extend(chars.next()),
+            '=' if token.key.is_none() && !quoted => {
+                token.key = Some(std::mem::take(&mut token.value));
+            }
+            _ => token.value.push(c),
+        }
+    }
+    tokens.extend(current);
+    Ok(tokens)
+}
+
+/// Quotes `value` if needed so that `tokenize` reads it back as a single word.
+pub fn quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '\'' | '"' | '\\' | '='));
+    if plain {
+        return value.to_string();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Looks up the command of `line` in the registry and collects its arguments.
+pub fn parse(line: &str) -> Result<(&'static Command, Args), ParseError> {
+    let mut tokens = tokenize(line)?.into_iter();
+    let word = match tokens.next() {
+        Some(Token { key: None, value }) => value,
+        Some(Token {
+            key: Some(key),
+            value,
+        }) => return Err(ParseError::UnknownCommand(format!("{}={}", key, value))),
+        None => return Err(ParseError::Empty),
+    };
+    let command = registry::find(&word).ok_or(ParseError::UnknownCommand(word))?;
+
+    let mut args = Args {
+        command,
+        positional: VecDeque::new(),
+        named: vec![],
+    };
+    for token in tokens {
+        match token.key {
+            Some(key) if !command.accepts(&key) => return Err(ParseError::UnknownOption(key)),
+            Some(key) => args.named.push((key, token.value)),
+            None => args.positional.push_back(token.value),
+        }
+    }
+    Ok((command, args))
+}
+
+/// The arguments of a command, consumed by name or position by the command handlers.
+pub struct Args {
+    command: &'static Command,
+    positional: VecDeque<String>,
+    named: Vec<(String, String)>,
+}
+
+impl Args {
+    /// `name=value` if given, otherwise the next positional argument.
+    pub fn required(&mut self, name: &'static str) -> Result<String, ParseError> {
+        self.optional(name)
+            .ok_or_else(|| ParseError::MissingArgument {
+                name,
+                usage: self.command.usage(),
+            })
+    }
+
+    /// Like `required`, for arguments that may be left out.
+    pub fn optional(&mut self, name: &str) -> Option<String> {
+        self.named(name).or_else(|| self.positional.pop_front())
+    }
+
+    pub fn required_parsed<T: FromStr>(
+        &mut self,
+        name: &'static str,
+        expected: &'static str,
+    ) -> Result<T, ParseError> {
+        let value = self.required(name)?;
+        value
+            .parse()
+            .map_err(|_| ParseError::invalid(name, &value, expected))
+    }
+
+    /// Removes the `key=value` argument, the last one wins if it was given more than once.
+    pub fn named(&mut self, key: &str) -> Option<String> {
+        let mut found = None;
+        self.named.retain(|(k, v)| {
+            if k == key {
+                found = Some(v.clone());
+                false
+            } else {
+                true
+            }
+        });
+        found
+    }
+
+    pub fn take_named(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.named)
+    }
+
+    pub fn take_positional(&mut self) -> Vec<String> {
+        self.positional.drain(..).collect()
+    }
+
+    /// Fails on arguments no handler asked for.
+    pub fn finish(mut self) -> Result<(), ParseError> {
+        if let Some(arg) = self.positional.pop_front() {
+            return Err(ParseError::UnexpectedArgument(arg));
+        }
+        if let Some((key, _)) = self.named.pop() {
+            return Err(ParseError::UnknownOption(key));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(key: Option<&str>, value: &str) -> Token {
+        Token {
+            key: key.map(|k| k.to_string()),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize(r#"ae "Coffee beans" f  3.50"#).unwrap(),
+            vec![
+                token(None, "ae"),
+                token(None, "Coffee beans"),
+                token(None, "f"),
+                token(None, "3.50"),
+            ]
+        );
+        assert_eq!(
+            tokenize(r#"name='Tom\s' note="say \"hi\"" "a=b" currency="#).unwrap(),
+            vec![
+                token(Some("name"), r"Tom\s"),
+                token(Some("note"), r#"say "hi""#),
+                token(None, "a=b"),
+                token(Some("currency"), ""),
+            ]
+        );
+        assert_eq!(
+            tokenize("ae \"Coffee"),
+            Err(ParseError::UnterminatedQuote('"'))
+        );
+        assert!(tokenize("  ").unwrap().is_empty());
+
+        for value in ["Coffee beans", "", r#"a "b" \c"#, "x=y", "plain"] {
+            let tokens = tokenize(&format!("name={}", quote(value))).unwrap();
+            assert_eq!(tokens, vec![token(Some("name"), value)]);
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let (command, mut args) = parse("ae amount=3 Coffee f extra").unwrap();
+        assert_eq!(command.name, "add-expense");
+        assert_eq!(args.required("name").unwrap(), "Coffee");
+        assert_eq!(args.required("category").unwrap(), "f");
+        assert_eq!(args.required_parsed::<u32>("amount", "a number"), Ok(3));
+        assert_eq!(
+            args.finish(),
+            Err(ParseError::UnexpectedArgument("extra".to_string()))
+        );
+
+        let (_, mut args) = parse("add-category Food").unwrap();
+        assert_eq!(args.required("name").unwrap(), "Food");
+        assert_eq!(
+            args.required("token"),
+            Err(ParseError::MissingArgument {
+                name: "token",
+                usage: "add-category <name> <token> [parent=..] [limit=..]".to_string()
+            })
+        );
+
+        let (_, mut args) = parse("de x").unwrap();
+        assert_eq!(
+            args.required_parsed::<u64>("id", "a number"),
+            Err(ParseError::invalid("id", "x", "a number"))
+        );
+        assert_eq!(
+            parse("ae colour=red").err(),
+            Some(ParseError::UnknownOption("colour".to_string()))
+        );
+        assert_eq!(
+            parse("xx").err(),
+            Some(ParseError::UnknownCommand("xx".to_string()))
+        );
+        assert_eq!(parse("").err(), Some(ParseError::Empty));
+    }
+}
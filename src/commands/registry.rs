@@ -0,0 +1,129 @@
+use anyhow::Result;
+
+use super::parser::Args;
+use crate::db::Storage;
+
+/// Runs a command and returns text to show the user, if any.
+pub type Handler = fn(Args, &dyn Storage) -> Result<Option<String>>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArgKind {
+    /// Given by position or as `name=value`.
+    Required,
+    /// Bare words after the required arguments, like a currency code.
+    Optional,
+    /// Only given as `name=value`.
+    Named,
+}
+
+#[derive(Clone, Copy)]
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub help: &'static str,
+}
+
+impl Arg {
+    pub const fn required(name: &'static str, help: &'static str) -> Self {
+        Arg {
+            name,
+            kind: ArgKind::Required,
+            help,
+        }
+    }
+
+    pub const fn optional(name: &'static str, help: &'static str) -> Self {
+        Arg {
+            name,
+            kind: ArgKind::Optional,
+            help,
+        }
+    }
+
+    pub const fn named(name: &'static str, help: &'static str) -> Self {
+        Arg {
+            name,
+            kind: ArgKind::Named,
+            help,
+        }
+    }
+
+    fn usage(&self) -> String {
+        match self.kind {
+            ArgKind::Required => format!("<{}>", self.name),
+            ArgKind::Optional => format!("[{}]", self.name),
+            ArgKind::Named => format!("[{}=..]", self.name),
+        }
+    }
+}
+
+/// A command with everything the parser and the help need to know about it.
+pub struct Command {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub args: &'static [Arg],
+    pub help: &'static str,
+    pub run: Handler,
+}
+
+impl Command {
+    pub fn matches(&self, word: &str) -> bool {
+        self.name == word || self.aliases.contains(&word)
+    }
+
+    /// Whether `key=value` is one of the arguments.
+    pub fn accepts(&self, key: &str) -> bool {
+        self.args.iter().any(|a| a.name == key)
+    }
+
+    /// E.g. `add-expense <name> <category> <amount> [currency]`.
+    pub fn usage(&self) -> String {
+        let mut words = vec![self.name.to_string()];
+        words.extend(self.args.iter().map(|a| a.usage()));
+        words.join(" ")
+    }
+
+    pub fn help_page(&self) -> String {
+        let mut lines = vec![format!("{}: {}", self.names(), self.help)];
+        lines.push(String::new());
+        lines.push(format!("Usage: {}", self.usage()));
+        let width = self.args.iter().map(|a| a.usage().len()).max().unwrap_or(0);
+        for arg in self.args {
+            lines.push(format!(
+                "  {:width$}  {}",
+                arg.usage(),
+                arg.help,
+                width = width
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// E.g. `add-expense (ae)`.
+    pub fn names(&self) -> String {
+        match self.aliases {
+            [] => self.name.to_string(),
+            aliases => format!("{} ({})", self.name, aliases.join(", ")),
+        }
+    }
+}
+
+pub fn find(word: &str) -> Option<&'static Command> {
+    super::COMMANDS.iter().find(|c| c.matches(word))
+}
+
+/// One line per command, for `help` without a topic.
+pub fn overview() -> String {
+    let width = super::COMMANDS
+        .iter()
+        .map(|c| c.names().len())
+        .max()
+        .unwrap_or(0);
+    let mut lines: Vec<String> = super::COMMANDS
+        .iter()
+        .map(|c| format!("{:width$}  {}", c.names(), c.help, width = width))
+        .collect();
+    lines.push(String::new());
+    lines.push("help <command> shows the arguments of a command".to_string());
+    lines.join("\n")
+}
@@ -1,243 +1,821 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDate;
 
 use crate::{
-    db,
-    model::{BookingType, BudgetBooking, Category, RecurringBooking, RecurringType},
+    db::Storage,
+    model::{
+        category_path, Assignment, BookingType, BudgetBooking, Category, PeriodSummary,
+        RecurringBooking,
+    },
+    money::Money,
+    period::Period,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 pub struct CategorySum {
+    /// Tokens from the top-level category down to this one, empty for the budget expenses.
+    pub path: Vec<String>,
+    pub name: String,
+    pub amount: Money,
+}
+
+/// A category of `category_tree`, listed before its subcategories.
+pub struct CategoryNode<'a> {
+    /// Tokens from the top-level category down to this one.
+    pub path: Vec<String>,
+    /// Items booked directly into this category.
+    pub items: Vec<&'a RecurringBooking>,
+    /// Monthly share of the items in this category and below it in the base currency, expenses
+    /// count negative.
+    pub total: Money,
+}
+
+impl CategoryNode<'_> {
+    pub fn token(&self) -> &str {
+        self.path.last().map(|t| t.as_str()).unwrap_or_default()
+    }
+
+    /// Number of parent categories, 0 for top-level ones.
+    pub fn depth(&self) -> usize {
+        self.path.len().saturating_sub(1)
+    }
+}
+
+/// Spending in a category with a monthly limit during the selected period.
+pub struct CategoryBudget {
     pub name: String,
-    pub amount: f32,
+    pub limit: Money,
+    /// Bookings in the category and its subcategories less refunds, in the base currency.
+    /// Recurring items are planned separately and don't count.
+    pub spent: Money,
+}
+
+impl CategoryBudget {
+    pub fn remaining(&self) -> Money {
+        self.limit - self.spent
+    }
+
+    pub fn percent(&self) -> u32 {
+        (self.spent.ratio_of(self.limit).max(0.0) * 100.0).round() as u32
+    }
+
+    pub fn is_exceeded(&self) -> bool {
+        self.spent > self.limit
+    }
+}
+
+/// The money of a category in envelope mode for the selected period, in the base currency.
+pub struct Envelope {
+    pub token: String,
+    /// Left over from earlier periods, negative after overspending.
+    pub carried: Money,
+    pub assigned: Money,
+    /// Bookings in the category, including those of subcategories without an envelope of their
+    /// own.
+    pub spent: Money,
+}
+
+impl Envelope {
+    pub fn available(&self) -> Money {
+        self.carried + self.assigned - self.spent
+    }
 }
 
 #[derive(Default)]
 pub struct DataService {
-    pub total_income: f32,
-    pub total_reccuring_expenses: f32,
-    pub total_budget_spent: f32,
-    pub total_budget_left: f32,
+    pub total_income: Money,
+    pub total_reccuring_expenses: Money,
+    pub total_budget_spent: Money,
+    pub total_budget_left: Money,
+    /// Categories with a limit, in the order they were added.
+    pub category_budgets: Vec<CategoryBudget>,
+    /// Income is assigned to categories each period instead of being planned.
+    pub envelope_mode: bool,
+    /// Envelopes with money or spending up to the selected period, by token.
+    pub envelopes: Vec<Envelope>,
+    /// Income booked up to the end of the selected period that isn't in an envelope yet.
+    pub total_unassigned: Money,
+    /// The budget period totals and the Budget tab are calculated for.
+    pub period: Period,
+    period_start_day: u32,
     recurring_bookings: Vec<RecurringBooking>,
     budget_bookings: Vec<BudgetBooking>,
     budget_bookings_archive: Vec<BudgetBooking>,
     categories: Vec<Category>,
+    assignments: Vec<Assignment>,
+    /// Symbol of the base currency shown next to amounts.
+    pub currency_symbol: String,
+    base_currency: String,
+    exchange_rates: HashMap<String, f64>,
 }
 
 impl DataService {
-    pub fn new() -> Self {
+    /// Loads the data for the current period, `calculate` has to follow before totals are used.
+    pub fn new(storage: &dyn Storage) -> Result<Self> {
         let mut data_service = DataService::default();
-        data_service.load_data().expect("Issue loading data");
-        data_service.calculate().expect("Issue calculating");
-        data_service
+        data_service.load_data(storage)?;
+        data_service.period = Period::current(data_service.period_start_day);
+        Ok(data_service)
     }
 
-    pub fn load_data(&mut self) -> Result<()> {
-        self.recurring_bookings = db::get_recurring()?;
-        self.budget_bookings = db::get_expenses()?;
-        self.budget_bookings_archive = db::get_expenses_archive()?;
-        self.categories = db::get_categories()?;
+    pub fn load_data(&mut self, storage: &dyn Storage) -> Result<()> {
+        self.recurring_bookings = storage.get_recurring()?;
+        self.budget_bookings = storage.get_expenses()?;
+        self.budget_bookings_archive = storage.get_expenses_archive()?;
+        self.categories = storage.get_categories()?;
+        self.assignments = storage.get_assignments()?;
+        self.envelope_mode = storage.get_setting_envelope_mode()?;
+        self.currency_symbol = storage.get_setting_currency_symbol()?;
+        self.base_currency = storage.get_setting_base_currency()?;
+        self.period_start_day = storage.get_setting_period_start_day()?;
+        self.exchange_rates = storage
+            .get_exchange_rates()?
+            .into_iter()
+            .map(|r| (r.currency, r.rate))
+            .collect();
         Ok(())
     }
 
-    pub fn calculate_reccuring_categorie_sums(&self) -> Result<Vec<CategorySum>> {
-        let mut result = vec![];
-        let mut categories: Vec<String> = self
-            .recurring_bookings
-            .iter()
-            .map(|c| c.category_token.to_string())
-            .collect();
-        categories.sort();
-        categories.dedup();
-
-        for cat in categories {
-            let rec: Vec<&RecurringBooking> = self
-                .recurring_bookings
-                .iter()
-                .filter(|c| c.category_token == cat)
-                .collect();
-
-            let monthly_sum: f32 = rec
-                .iter()
-                .filter(|e| e.rate_type == RecurringType::Monthly)
-                .map(|c| c.amount)
-                .sum();
-
-            let yearly_sum: f32 = rec
-                .iter()
-                .filter(|e| e.rate_type == RecurringType::Yearly)
-                .map(|c| c.amount)
-                .sum();
-
-            let mut sum = monthly_sum + (yearly_sum / 12.0);
-
-            if rec.first().unwrap().kind == BookingType::Expense {
-                sum *= -1.0;
-            }
+    pub fn is_base_currency(&self, currency: &str) -> bool {
+        currency.is_empty() || currency == self.base_currency
+    }
 
-            result.push(CategorySum {
-                name: cat,
-                amount: sum,
-            });
+    /// Converts an amount in `currency` into the base currency using the exchange rate table.
+    pub fn in_base(&self, amount: Money, currency: &str) -> Result<Money> {
+        if self.is_base_currency(currency) {
+            return Ok(amount);
+        }
+        match self.exchange_rates.get(currency) {
+            Some(rate) => Ok(amount.convert(*rate)),
+            None => Err(anyhow!("No exchange rate for {}", currency)),
         }
+    }
+
+    /// Monthly sums of the recurring items per category, each followed by its subcategories, and
+    /// the budget expenses.
+    pub fn calculate_reccuring_categorie_sums(&self) -> Result<Vec<CategorySum>> {
+        let mut result: Vec<CategorySum> = self
+            .category_tree(self.active_recurring().collect())?
+            .into_iter()
+            .map(|node| CategorySum {
+                name: self
+                    .category_name(node.token())
+                    .unwrap_or(node.token())
+                    .to_string(),
+                path: node.path,
+                amount: node.total,
+            })
+            .collect();
 
         let expenses = CategorySum {
+            path: vec![],
             name: "Budget Expenses".to_string(),
-            amount: self.total_budget_spent * -1.0,
+            amount: -self.total_budget_spent,
         };
         result.push(expenses);
 
         Ok(result)
     }
 
-    pub fn get_recurring(&self, kind: BookingType) -> Result<Vec<&RecurringBooking>> {
-        let recurring = self
-            .recurring_bookings
+    /// The categories of `items` and their parents with subtotals, top-level categories first and
+    /// each followed by its subcategories, ordered by token.
+    pub fn category_tree<'b>(
+        &self,
+        items: Vec<&'b RecurringBooking>,
+    ) -> Result<Vec<CategoryNode<'b>>> {
+        let mut nodes: BTreeMap<Vec<String>, CategoryNode> = BTreeMap::new();
+        for item in items {
+            let mut amount =
+                self.in_base(item.recurrence.monthly_amount(item.amount), &item.currency)?;
+            if item.kind == BookingType::Expense {
+                amount = -amount;
+            }
+            let path: Vec<String> = category_path(&self.categories, &item.category_token)
+                .into_iter()
+                .map(|t| t.to_string())
+                .collect();
+            for len in 1..=path.len() {
+                let node = nodes
+                    .entry(path[..len].to_vec())
+                    .or_insert_with(|| CategoryNode {
+                        path: path[..len].to_vec(),
+                        items: vec![],
+                        total: Money::ZERO,
+                    });
+                node.total += amount;
+                if len == path.len() {
+                    node.items.push(item);
+                }
+            }
+        }
+        Ok(nodes.into_values().collect())
+    }
+
+    /// Recurring items that have not ended before the selected period.
+    fn active_recurring(&self) -> impl Iterator<Item = &RecurringBooking> {
+        self.recurring_bookings
             .iter()
-            .filter(|c| c.kind == kind)
-            .collect();
-        Ok(recurring)
+            .filter(|r| !r.ended_before(self.period.start))
     }
 
-    pub fn get_all_bookings(&self) -> Result<&Vec<BudgetBooking>> {
-        Ok(&self.budget_bookings)
+    pub fn get_recurring(&self, kind: BookingType) -> Result<Vec<&RecurringBooking>> {
+        let recurring = self.active_recurring().filter(|c| c.kind == kind).collect();
+        Ok(recurring)
     }
 
-    pub fn get_bookings(&self, kind: BookingType) -> Result<Vec<&BudgetBooking>> {
+    /// All bookings, active and archived, that fall into the selected period.
+    pub fn get_period_bookings(&self) -> Result<Vec<&BudgetBooking>> {
         let bookings = self
             .budget_bookings
             .iter()
+            .chain(self.budget_bookings_archive.iter())
+            .filter(|b| self.period.contains(b.local_date()))
+            .collect();
+        Ok(bookings)
+    }
+
+    pub fn get_bookings(&self, kind: BookingType) -> Result<Vec<&BudgetBooking>> {
+        let bookings = self
+            .get_period_bookings()?
+            .into_iter()
             .filter(|i| i.kind == kind)
             .collect();
         Ok(bookings)
     }
 
-    pub fn get_bookings_archive(&self) -> Result<&Vec<BudgetBooking>> {
-        Ok(&self.budget_bookings_archive)
+    pub fn next_period(&mut self) {
+        self.period = self.period.next();
     }
 
-    pub fn get_categorie_map(&self) -> Result<HashMap<String, String>> {
-        let mut categorie_map = HashMap::new();
-        for c in &self.categories {
-            categorie_map.insert(c.token.clone(), c.name.clone());
-        }
-        Ok(categorie_map)
+    pub fn previous_period(&mut self) {
+        self.period = self.period.previous();
+    }
+
+    pub fn current_period(&mut self) {
+        self.period = Period::current(self.period_start_day);
+    }
+
+    pub fn period_containing(&self, date: NaiveDate) -> Period {
+        Period::containing(date, self.period_start_day)
+    }
+
+    /// Selects `period` and returns its totals.
+    pub fn summarize(&mut self, period: Period) -> Result<PeriodSummary> {
+        self.period = period;
+        self.calculate()?;
+        Ok(PeriodSummary {
+            start: period.start,
+            end: period.last_day(),
+            income: self.total_income,
+            recurring_expenses: self.total_reccuring_expenses,
+            budget_spent: self.total_budget_spent,
+            budget_left: self.total_budget_left,
+        })
+    }
+
+    /// The archived bookings outside the selected period, the ones inside are listed with it.
+    pub fn get_bookings_archive(&self) -> Result<Vec<&BudgetBooking>> {
+        let bookings = self
+            .budget_bookings_archive
+            .iter()
+            .filter(|b| !self.period.contains(b.local_date()))
+            .collect();
+        Ok(bookings)
+    }
+
+    pub fn category(&self, token: &str) -> Option<&Category> {
+        self.categories.iter().find(|c| c.token == token)
+    }
+
+    pub fn category_name(&self, token: &str) -> Option<&str> {
+        self.category(token).map(|c| c.name.as_str())
+    }
+
+    /// Sums the monthly share of each recurring item in the base currency.
+    fn sum_monthly_in_base<'b>(
+        &self,
+        recurring: impl Iterator<Item = &'b RecurringBooking>,
+    ) -> Result<Money> {
+        recurring
+            .map(|r| self.in_base(r.recurrence.monthly_amount(r.amount), &r.currency))
+            .sum()
+    }
+
+    fn sum_in_base<'b>(&self, amounts: impl Iterator<Item = (Money, &'b String)>) -> Result<Money> {
+        amounts
+            .map(|(amount, currency)| self.in_base(amount, currency))
+            .sum()
     }
 
     pub fn calculate(&mut self) -> Result<()> {
         let recurring_expense_bookings = self.get_recurring(BookingType::Expense)?;
         let budget_bookings = self.get_bookings(BookingType::Expense)?;
         let budget_bookins_income = self.get_bookings(BookingType::Income)?;
-        let (monthly_bookings, yearly_bookings): (Vec<&RecurringBooking>, Vec<&RecurringBooking>) =
-            recurring_expense_bookings
-                .into_iter()
-                .partition(|r| r.rate_type == RecurringType::Monthly);
         let income_bookings = self.get_recurring(BookingType::Income)?;
 
-        let income = income_bookings.iter().map(|i| i.amount).sum();
-        let monthly: f32 = monthly_bookings.iter().map(|i| i.amount).sum();
-        let yearly: f32 = yearly_bookings.iter().map(|i| i.amount).sum::<f32>() / 12.0;
-        let budget_spent: f32 = budget_bookings.iter().map(|b| b.amount).sum();
-        let budget_income: f32 = budget_bookins_income.iter().map(|b| b.amount).sum();
+        let income = self.sum_monthly_in_base(income_bookings.into_iter())?;
+        let recurring_expenses =
+            self.sum_monthly_in_base(recurring_expense_bookings.into_iter())?;
+        // Scheduled recurring bookings are already part of the planned totals above
+        let budget_spent = self.sum_in_base(
+            budget_bookings
+                .iter()
+                .filter(|b| !b.recurring)
+                .map(|b| (b.amount, &b.currency)),
+        )?;
+        let budget_income = self.sum_in_base(
+            budget_bookins_income
+                .iter()
+                .filter(|b| !b.recurring)
+                .map(|b| (b.amount, &b.currency)),
+        )?;
 
         self.total_income = income;
-        self.total_reccuring_expenses = yearly + monthly;
-        self.total_budget_left = income - (monthly + yearly + budget_spent - budget_income);
+        self.total_reccuring_expenses = recurring_expenses;
+        self.total_budget_left = income - (recurring_expenses + budget_spent - budget_income);
         self.total_budget_spent = budget_spent - budget_income;
+        self.category_budgets = self.calculate_category_budgets()?;
+        if self.envelope_mode {
+            self.calculate_envelopes()?;
+        }
+
+        Ok(())
+    }
+
+    fn calculate_category_budgets(&self) -> Result<Vec<CategoryBudget>> {
+        let bookings: Vec<&BudgetBooking> = self
+            .get_period_bookings()?
+            .into_iter()
+            .filter(|b| !b.recurring)
+            .collect();
+        let mut budgets = vec![];
+        for category in &self.categories {
+            let limit = match category.limit {
+                Some(limit) => limit,
+                None => continue,
+            };
+            let mut spent = Money::ZERO;
+            for b in &bookings {
+                if !category_path(&self.categories, &b.category_token).contains(&&*category.token) {
+                    continue;
+                }
+                let amount = self.in_base(b.amount, &b.currency)?;
+                match b.kind {
+                    BookingType::Expense => spent += amount,
+                    BookingType::Income => spent -= amount,
+                }
+            }
+            budgets.push(CategoryBudget {
+                name: category.name.clone(),
+                limit,
+                spent,
+            });
+        }
+        Ok(budgets)
+    }
+
+    /// Rolls the assignments and bookings of all periods up to the selected one into the
+    /// envelopes. Recurring bookings count like any other, as nothing is planned in this mode.
+    fn calculate_envelopes(&mut self) -> Result<()> {
+        let mut envelopes: BTreeMap<String, Envelope> = BTreeMap::new();
+        let mut income = Money::ZERO;
+        let mut assigned_total = Money::ZERO;
+        for a in self
+            .assignments
+            .iter()
+            .filter(|a| a.period < self.period.end)
+        {
+            assigned_total += a.amount;
+            let envelope = envelope(&mut envelopes, &a.category_token);
+            if a.period < self.period.start {
+                envelope.carried += a.amount;
+            } else {
+                envelope.assigned += a.amount;
+            }
+        }
+
+        for b in self
+            .budget_bookings
+            .iter()
+            .chain(self.budget_bookings_archive.iter())
+            .filter(|b| b.local_date() < self.period.end)
+        {
+            let amount = self.in_base(b.amount, &b.currency)?;
+            // Income, refunds included, is money to assign again
+            if b.kind == BookingType::Income {
+                income += amount;
+                continue;
+            }
+            // The nearest category up the tree with money assigned pays for the booking
+            let token = category_path(&self.categories, &b.category_token)
+                .into_iter()
+                .rev()
+                .find(|t| self.assignments.iter().any(|a| a.category_token == *t))
+                .unwrap_or(&b.category_token)
+                .to_string();
+            let envelope = envelope(&mut envelopes, &token);
+            if self.period.contains(b.local_date()) {
+                envelope.spent += amount;
+            } else {
+                envelope.carried -= amount;
+            }
+        }
 
+        self.envelopes = envelopes.into_values().collect();
+        self.total_unassigned = income - assigned_total;
         Ok(())
     }
 }
 
+fn envelope<'a>(envelopes: &'a mut BTreeMap<String, Envelope>, token: &str) -> &'a mut Envelope {
+    envelopes
+        .entry(token.to_string())
+        .or_insert_with(|| Envelope {
+            token: token.to_string(),
+            carried: Money::ZERO,
+            assigned: Money::ZERO,
+            spent: Money::ZERO,
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::MemoryStorage;
     use crate::model::BookingType::*;
+    use crate::model::Recurrence;
     use crate::model::RecurringType::*;
 
+    fn m(amount: &str) -> Money {
+        amount.parse().unwrap()
+    }
+
     #[test]
     fn test_simple_calculation() {
         let mut ds = DataService::default();
         let budget_bookings = vec![
-            BudgetBooking::new("T", Expense, "tt", 2.00),
-            BudgetBooking::new("T", Expense, "tt", 1.00),
-            BudgetBooking::new("T", Expense, "tt", 1.00),
-            BudgetBooking::new("T", Expense, "tt", 3.00),
-            BudgetBooking::new("T", Income, "tt", 3.00),
+            BudgetBooking::new("T", Expense, "tt", m("2.00")),
+            BudgetBooking::new("T", Expense, "tt", m("1.00")),
+            BudgetBooking::new("T", Expense, "tt", m("1.00")),
+            BudgetBooking::new("T", Expense, "tt", m("3.00")),
+            BudgetBooking::new("T", Income, "tt", m("3.00")),
         ];
         let recurring_bookings = vec![
-            RecurringBooking::new("Ti", Expense, "tt", 1.00, Monthly),
-            RecurringBooking::new("Ti", Expense, "tt", 12.00, Yearly),
-            RecurringBooking::new("Ti", Income, "tt", 10.00, Monthly),
+            RecurringBooking::new("Ti", Expense, "tt", m("1.00"), Recurrence::new(Monthly, 1)),
+            RecurringBooking::new("Ti", Expense, "tt", m("12.00"), Recurrence::new(Yearly, 1)),
+            RecurringBooking::new("Ti", Income, "tt", m("10.00"), Recurrence::new(Monthly, 1)),
         ];
 
         ds.budget_bookings = budget_bookings;
         ds.recurring_bookings = recurring_bookings;
         ds.calculate().unwrap();
 
-        assert_eq!(ds.total_budget_left, 4.00);
-        assert_eq!(ds.total_income, 10.00);
-        assert_eq!(ds.total_reccuring_expenses, 2.00);
-        assert_eq!(ds.total_budget_spent, 4.00);
+        assert_eq!(ds.total_budget_left, m("4.00"));
+        assert_eq!(ds.total_income, m("10.00"));
+        assert_eq!(ds.total_reccuring_expenses, m("2.00"));
+        assert_eq!(ds.total_budget_spent, m("4.00"));
     }
 
     #[test]
     fn test_complex_calculation() {
         let mut ds = DataService::default();
         let budget_bookings = vec![
-            BudgetBooking::new("E1", Expense, "tt", 3.00),
-            BudgetBooking::new("E2", Expense, "tt", 33.12),
-            BudgetBooking::new("E3", Expense, "tt", 13.49),
-            BudgetBooking::new("E4", Expense, "tt", 32.00),
-            BudgetBooking::new("E5", Expense, "tt", 22.22),
-            BudgetBooking::new("E6", Expense, "tt", 750.00),
-            BudgetBooking::new("E7", Expense, "tt", 123.01),
-            BudgetBooking::new("I1", Income, "tt", 10.00),
-            BudgetBooking::new("I2", Income, "tt", 33.33),
-            BudgetBooking::new("I3", Income, "tt", 5.49),
+            BudgetBooking::new("E1", Expense, "tt", m("3.00")),
+            BudgetBooking::new("E2", Expense, "tt", m("33.12")),
+            BudgetBooking::new("E3", Expense, "tt", m("13.49")),
+            BudgetBooking::new("E4", Expense, "tt", m("32.00")),
+            BudgetBooking::new("E5", Expense, "tt", m("22.22")),
+            BudgetBooking::new("E6", Expense, "tt", m("750.00")),
+            BudgetBooking::new("E7", Expense, "tt", m("123.01")),
+            BudgetBooking::new("I1", Income, "tt", m("10.00")),
+            BudgetBooking::new("I2", Income, "tt", m("33.33")),
+            BudgetBooking::new("I3", Income, "tt", m("5.49")),
         ];
         let recurring_bookings = vec![
-            RecurringBooking::new("Income", Income, "tt", 4000.00, Monthly),
-            RecurringBooking::new("M1", Expense, "tt", 1.00, Monthly),
-            RecurringBooking::new("M2", Expense, "tt", 2.00, Monthly),
-            RecurringBooking::new("M3", Expense, "tt", 22.50, Monthly),
-            RecurringBooking::new("M4", Expense, "tt", 11.00, Monthly),
-            RecurringBooking::new("M5", Expense, "tt", 21.85, Monthly),
-            RecurringBooking::new("M6", Expense, "tt", 7.01, Monthly),
-            RecurringBooking::new("M7", Expense, "tt", 41.00, Monthly),
-            RecurringBooking::new("M8", Expense, "tt", 600.00, Monthly),
-            RecurringBooking::new("M9", Expense, "tt", 1001.11, Monthly),
-            RecurringBooking::new("Y1", Expense, "tt", 12.00, Yearly),
-            RecurringBooking::new("Y2", Expense, "tt", 389.00, Yearly),
-            RecurringBooking::new("Y3", Expense, "tt", 72.22, Yearly),
+            RecurringBooking::new(
+                "Income",
+                Income,
+                "tt",
+                m("4000.00"),
+                Recurrence::new(Monthly, 1),
+            ),
+            RecurringBooking::new("M1", Expense, "tt", m("1.00"), Recurrence::new(Monthly, 1)),
+            RecurringBooking::new("M2", Expense, "tt", m("2.00"), Recurrence::new(Monthly, 1)),
+            RecurringBooking::new("M3", Expense, "tt", m("22.50"), Recurrence::new(Monthly, 1)),
+            RecurringBooking::new("M4", Expense, "tt", m("11.00"), Recurrence::new(Monthly, 1)),
+            RecurringBooking::new("M5", Expense, "tt", m("21.85"), Recurrence::new(Monthly, 1)),
+            RecurringBooking::new("M6", Expense, "tt", m("7.01"), Recurrence::new(Monthly, 1)),
+            RecurringBooking::new("M7", Expense, "tt", m("41.00"), Recurrence::new(Monthly, 1)),
+            RecurringBooking::new(
+                "M8",
+                Expense,
+                "tt",
+                m("600.00"),
+                Recurrence::new(Monthly, 1),
+            ),
+            RecurringBooking::new(
+                "M9",
+                Expense,
+                "tt",
+                m("1001.11"),
+                Recurrence::new(Monthly, 1),
+            ),
+            RecurringBooking::new("Y1", Expense, "tt", m("12.00"), Recurrence::new(Yearly, 1)),
+            RecurringBooking::new("Y2", Expense, "tt", m("389.00"), Recurrence::new(Yearly, 1)),
+            RecurringBooking::new("Y3", Expense, "tt", m("72.22"), Recurrence::new(Yearly, 1)),
         ];
 
         ds.budget_bookings = budget_bookings;
         ds.recurring_bookings = recurring_bookings;
         ds.calculate().unwrap();
 
-        assert_eq!(ds.total_budget_left, 1325.075);
-        assert_eq!(ds.total_income, 4000.00);
-        assert_eq!(ds.total_reccuring_expenses, 1746.905);
-        assert_eq!(ds.total_budget_spent, 928.02);
+        // each yearly item is rounded on its own: 1.00 + 32.42 (389 / 12) + 6.02 (72.22 / 12)
+        assert_eq!(ds.total_budget_left, m("1325.07"));
+        assert_eq!(ds.total_income, m("4000.00"));
+        assert_eq!(ds.total_reccuring_expenses, m("1746.91"));
+        assert_eq!(ds.total_budget_spent, m("928.02"));
+    }
+
+    #[test]
+    fn test_multi_currency_calculation() {
+        let mut ds = DataService {
+            base_currency: "EUR".to_string(),
+            exchange_rates: HashMap::from([("USD".to_string(), 0.5)]),
+            ..Default::default()
+        };
+        ds.budget_bookings = vec![
+            BudgetBooking::new("E1", Expense, "tt", m("10.00")).in_currency("USD"),
+            BudgetBooking::new("E2", Expense, "tt", m("1.00")).in_currency("EUR"),
+            BudgetBooking::new("E3", Expense, "tt", m("1.00")),
+        ];
+        ds.recurring_bookings = vec![
+            RecurringBooking::new(
+                "Income",
+                Income,
+                "tt",
+                m("100.00"),
+                Recurrence::new(Monthly, 1),
+            )
+            .in_currency("USD"),
+            RecurringBooking::new("Y1", Expense, "tt", m("24.00"), Recurrence::new(Yearly, 1))
+                .in_currency("USD"),
+        ];
+        ds.calculate().unwrap();
+
+        assert_eq!(ds.total_income, m("50.00"));
+        assert_eq!(ds.total_reccuring_expenses, m("1.00"));
+        assert_eq!(ds.total_budget_spent, m("7.00"));
+        assert_eq!(ds.total_budget_left, m("42.00"));
+
+        ds.budget_bookings
+            .push(BudgetBooking::new("E4", Expense, "tt", m("1.00")).in_currency("CHF"));
+        assert!(ds.calculate().is_err());
+    }
+
+    #[test]
+    fn test_only_selected_period_is_calculated() {
+        let booking_on = |name: &str, amount: &str, day: &str| {
+            let mut b = BudgetBooking::new(name, Expense, "tt", m(amount));
+            b.date = format!("{}T12:00:00Z", day).parse().unwrap();
+            b
+        };
+        let mut ds = DataService {
+            period: Period::containing(chrono::NaiveDate::from_ymd_opt(2026, 10, 14).unwrap(), 1),
+            ..Default::default()
+        };
+        ds.budget_bookings = vec![
+            booking_on("Sep", "5.00", "2026-09-15"),
+            booking_on("Oct", "1.00", "2026-10-15"),
+        ];
+        ds.budget_bookings_archive = vec![booking_on("Archived", "7.00", "2026-09-10")];
+
+        ds.calculate().unwrap();
+        assert_eq!(ds.total_budget_spent, m("1.00"));
+        assert_eq!(ds.get_bookings_archive().unwrap().len(), 1);
+
+        // archived bookings of the selected period are only listed with it
+        ds.previous_period();
+        ds.calculate().unwrap();
+        assert_eq!(ds.total_budget_spent, m("12.00"));
+        assert_eq!(ds.get_period_bookings().unwrap().len(), 2);
+        assert!(ds.get_bookings_archive().unwrap().is_empty());
+
+        ds.previous_period();
+        ds.calculate().unwrap();
+        assert_eq!(ds.total_budget_spent, m("0.00"));
+    }
+
+    #[test]
+    fn test_recurrence_is_normalized_to_months() {
+        let mut ended = RecurringBooking::new(
+            "Ended",
+            Expense,
+            "tt",
+            m("500.00"),
+            Recurrence {
+                count: Some(2),
+                ..Recurrence::new(Monthly, 1)
+            },
+        );
+        ended.anchor_date = chrono::NaiveDate::from_ymd_opt(2026, 1, 1);
+        let mut ds = DataService {
+            period: Period::containing(chrono::NaiveDate::from_ymd_opt(2026, 10, 14).unwrap(), 1),
+            ..Default::default()
+        };
+        ds.recurring_bookings = vec![
+            RecurringBooking::new(
+                "Salary",
+                Income,
+                "tt",
+                m("1000.00"),
+                Recurrence::new(Weekly, 2),
+            ),
+            RecurringBooking::new(
+                "Insurance",
+                Expense,
+                "tt",
+                m("90.00"),
+                Recurrence::new(Monthly, 3),
+            ),
+            RecurringBooking::new("Gym", Expense, "tt", m("40.00"), Recurrence::new(Weekly, 4)),
+            RecurringBooking::new(
+                "Tax",
+                Expense,
+                "tt",
+                m("60.00"),
+                Recurrence::new(Monthly, 6),
+            ),
+            ended,
+        ];
+
+        ds.calculate().unwrap();
+        // 1000 * 365.2425 / 14 / 12 = 2174.0625
+        assert_eq!(ds.total_income, m("2174.06"));
+        // 30 + 43.48 + 10, the ended item no longer counts
+        assert_eq!(ds.total_reccuring_expenses, m("83.48"));
     }
 
     #[test]
     fn test_categories() {
-        let mut ds = DataService::default();
-        let categories = vec![
+        let storage = MemoryStorage::default();
+        for category in [
             Category::new("Cat A", "a"),
             Category::new("b", "b"),
-            Category::new("c", "c"),
             Category::new("Cat D", "dd"),
-            Category::new("e", "e"),
             Category::new("f", "ff"),
+        ] {
+            storage.add_category(category).unwrap();
+        }
+        let mut ds = DataService::new(&storage).unwrap();
+
+        assert_eq!(ds.category_name("a"), Some("Cat A"));
+        assert_eq!(ds.category_name("dd"), Some("Cat D"));
+        assert_eq!(ds.category_name("ff"), Some("f"));
+        assert_eq!(ds.category_name("g"), None);
+
+        // categories added later show up after reloading
+        storage.add_category(Category::new("Gifts", "g")).unwrap();
+        ds.load_data(&storage).unwrap();
+        assert_eq!(ds.category_name("g"), Some("Gifts"));
+        assert_eq!(
+            ds.currency_symbol,
+            storage.get_setting_currency_symbol().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_category_tree() {
+        let mut ds = DataService::default();
+        let category = |name: &str, token: &str, parent: Option<&str>| {
+            let mut c = Category::new(name, token);
+            c.parent = parent.map(|p| p.to_string());
+            c
+        };
+        ds.categories = vec![
+            category("Housing", "h", None),
+            category("Rent", "r", Some("h")),
+            category("Utilities", "u", Some("h")),
+            category("Power", "p", Some("u")),
+            category("Salary", "s", None),
+        ];
+        let monthly = |name: &str, kind: BookingType, token: &str, amount: &str| {
+            RecurringBooking::new(name, kind, token, m(amount), Recurrence::new(Monthly, 1))
+        };
+        ds.recurring_bookings = vec![
+            monthly("Flat", Expense, "r", "800.00"),
+            monthly("Water", Expense, "u", "20.00"),
+            monthly("Power", Expense, "p", "60.00"),
+            monthly("Insurance", Expense, "h", "15.00"),
+            monthly("Job", Income, "s", "3000.00"),
+            monthly("Gift", Expense, "x", "10.00"),
         ];
-        ds.categories = categories;
-        let map = ds.get_categorie_map().unwrap();
+        ds.calculate().unwrap();
+
+        let sums: Vec<(Vec<String>, Money)> = ds
+            .calculate_reccuring_categorie_sums()
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.path, s.amount))
+            .collect();
+        let path = |tokens: &[&str]| tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            sums,
+            vec![
+                (path(&["h"]), m("-895.00")),
+                (path(&["h", "r"]), m("-800.00")),
+                (path(&["h", "u"]), m("-80.00")),
+                (path(&["h", "u", "p"]), m("-60.00")),
+                (path(&["s"]), m("3000.00")),
+                (path(&["x"]), m("-10.00")),
+                (vec![], m("0.00")),
+            ]
+        );
+
+        let tree = ds.category_tree(ds.active_recurring().collect()).unwrap();
+        assert_eq!(tree[0].items.len(), 1);
+        assert_eq!(tree[2].token(), "u");
+        assert_eq!(tree[3].depth(), 2);
+    }
 
-        assert_eq!(map.get("a").unwrap(), "Cat A");
-        assert_eq!(map.get("dd").unwrap(), "Cat D");
-        assert_eq!(map.get("ff").unwrap(), "f");
-        assert_eq!(map.get("g"), None);
+    #[test]
+    fn test_category_budgets() {
+        let mut ds = DataService::default();
+        let mut food = Category::new("Food", "f");
+        food.limit = Some(m("100.00"));
+        let mut snacks = Category::new("Snacks", "s");
+        snacks.parent = Some("f".to_string());
+        snacks.limit = Some(m("20.00"));
+        ds.categories = vec![food, snacks, Category::new("Rent", "r")];
+        ds.budget_bookings = vec![
+            BudgetBooking::new("Groceries", Expense, "f", m("60.00")),
+            BudgetBooking::new("Chips", Expense, "s", m("25.00")),
+            BudgetBooking::new("Refund", Income, "f", m("5.00")),
+            BudgetBooking::new("Flat", Expense, "r", m("800.00")),
+        ];
+        ds.current_period();
+        ds.calculate().unwrap();
+
+        let budgets = &ds.category_budgets;
+        assert_eq!(budgets.len(), 2);
+        assert_eq!(budgets[0].spent, m("80.00"));
+        assert_eq!(budgets[0].remaining(), m("20.00"));
+        assert_eq!(budgets[0].percent(), 80);
+        assert!(!budgets[0].is_exceeded());
+        assert_eq!(budgets[1].spent, m("25.00"));
+        assert_eq!(budgets[1].percent(), 125);
+        assert!(budgets[1].is_exceeded());
+    }
+
+    #[test]
+    fn test_envelopes() {
+        let mut ds = DataService::default();
+        let mut snacks = Category::new("Snacks", "s");
+        snacks.parent = Some("f".to_string());
+        ds.categories = vec![
+            Category::new("Food", "f"),
+            snacks,
+            Category::new("Fun", "u"),
+        ];
+        ds.envelope_mode = true;
+        ds.current_period();
+        let current = ds.period;
+        let assign = |period: &Period, token: &str, amount: &str| Assignment {
+            period: period.start,
+            category_token: token.to_string(),
+            amount: m(amount),
+        };
+        ds.assignments = vec![
+            assign(&current, "f", "100.00"),
+            assign(&current.next(), "f", "50.00"),
+            assign(&current.next().next(), "f", "999.00"),
+        ];
+        ds.budget_bookings = vec![
+            BudgetBooking::new("Salary", Income, "f", m("2000.00")),
+            BudgetBooking::new("Groceries", Expense, "f", m("60.00")),
+            BudgetBooking::new("Chips", Expense, "s", m("25.00")),
+            BudgetBooking::new("Refund", Income, "s", m("5.00")),
+            BudgetBooking::new("Bonus", Income, "u", m("100.00")),
+            BudgetBooking::new("Cinema", Expense, "u", m("12.00")),
+        ];
+        ds.calculate().unwrap();
+
+        assert_eq!(ds.envelopes.len(), 2);
+        let food = &ds.envelopes[0];
+        assert_eq!(food.token, "f");
+        assert_eq!(food.assigned, m("100.00"));
+        assert_eq!(food.spent, m("85.00"));
+        assert_eq!(food.available(), m("15.00"));
+        assert_eq!(ds.envelopes[1].available(), m("-12.00"));
+        assert_eq!(ds.total_unassigned, m("2005.00"));
+
+        // leftovers and deficits roll over into the next period
+        ds.next_period();
+        ds.calculate().unwrap();
+        assert_eq!(ds.envelopes[0].carried, m("15.00"));
+        assert_eq!(ds.envelopes[0].spent, Money::ZERO);
+        assert_eq!(ds.envelopes[0].available(), m("65.00"));
+        assert_eq!(ds.envelopes[1].carried, m("-12.00"));
+        assert_eq!(ds.total_unassigned, m("1955.00"));
     }
 }
@@ -0,0 +1,642 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    rc::{Rc, Weak},
+};
+
+use super::{assign_id, ensure_unique_token, lock::DirLock, next_id, not_found, Storage};
+use crate::journal::Journal;
+use crate::model::{
+    Assignment, BudgetBooking, Category, ExchangeRate, PeriodSummary, Record, RecurringBooking,
+    Setting,
+};
+
+const DB_FILE_CATEGORY: &str = "data_categories.json";
+const DB_FILE_SETTINGS: &str = "settings.json";
+const DB_FILE_BOOKINGS: &str = "data_bookings.json";
+const DB_FILE_BOOKINGS_ARCHIVE: &str = "data_bookings_archive.json";
+/// With `Archive_Split_Yearly` the archive is split into `data_bookings_archive_<year>.json` files.
+const DB_FILE_BOOKINGS_ARCHIVE_YEAR_PREFIX: &str = "data_bookings_archive_";
+const DB_FILE_PERIOD_SUMMARIES: &str = "data_period_summaries.json";
+const DB_FILE_RECURRING: &str = "data_recurring.json";
+const DB_FILE_EXCHANGE_RATES: &str = "data_exchange_rates.json";
+const DB_FILE_JOURNAL: &str = "data_journal.json";
+const DB_FILE_ASSIGNMENTS: &str = "data_assignments.json";
+/// The highest id ever handed out per collection, unlike the others an object.
+const DB_FILE_IDS: &str = "data_ids.json";
+
+/// Stores every entity as a pretty printed JSON array in its own file inside the data directory.
+pub struct JsonStorage {
+    base: PathBuf,
+    read_only: bool,
+    /// The lock while any call holds it, shared by nested calls.
+    held: RefCell<Weak<DirLock>>,
+}
+
+impl JsonStorage {
+    pub fn new(base: &Path) -> Result<Self> {
+        let storage = JsonStorage {
+            base: base.to_path_buf(),
+            read_only: false,
+            held: RefCell::default(),
+        };
+        let _lock = storage.lock()?;
+        storage.ensure_db_files_exist()?;
+        storage.migrate_float_amounts::<BudgetBooking>(DB_FILE_BOOKINGS)?;
+        storage.migrate_float_amounts::<BudgetBooking>(DB_FILE_BOOKINGS_ARCHIVE)?;
+        storage.migrate_float_amounts::<RecurringBooking>(DB_FILE_RECURRING)?;
+        storage.assign_missing_ids::<BudgetBooking>(&storage.booking_files()?, "bookings")?;
+        storage.assign_missing_ids::<RecurringBooking>(
+            &[DB_FILE_RECURRING.to_string()],
+            "recurring",
+        )?;
+        storage.assign_missing_ids::<Category>(&[DB_FILE_CATEGORY.to_string()], "categories")?;
+        Ok(storage)
+    }
+
+    /// Opens the data files without ever writing to them or taking the lock. Missing files read as
+    /// empty.
+    pub fn new_read_only(base: &Path) -> Self {
+        JsonStorage {
+            base: base.to_path_buf(),
+            read_only: true,
+            held: RefCell::default(),
+        }
+    }
+
+    /// Every read-modify-write has to hold this for its whole duration. Calls inside
+    /// `with_lock` get the lock already held.
+    fn lock(&self) -> Result<Rc<DirLock>> {
+        if self.read_only {
+            return Err(anyhow!("Data files are opened read-only"));
+        }
+        if let Some(lock) = self.held.borrow().upgrade() {
+            return Ok(lock);
+        }
+        let lock = Rc::new(DirLock::acquire(&self.base)?);
+        *self.held.borrow_mut() = Rc::downgrade(&lock);
+        Ok(lock)
+    }
+
+    fn ensure_db_files_exist(&self) -> Result<()> {
+        fs::create_dir_all(&self.base)?;
+        let cat_path = self.base.join(DB_FILE_CATEGORY);
+        let book_path = self.base.join(DB_FILE_BOOKINGS);
+        let rec_path = self.base.join(DB_FILE_RECURRING);
+        let set_path = self.base.join(DB_FILE_SETTINGS);
+        let rate_path = self.base.join(DB_FILE_EXCHANGE_RATES);
+        let archive_path = self.base.join(DB_FILE_BOOKINGS_ARCHIVE);
+        let summary_path = self.base.join(DB_FILE_PERIOD_SUMMARIES);
+        for path in [
+            cat_path,
+            book_path,
+            rec_path,
+            set_path,
+            rate_path,
+            archive_path,
+            summary_path,
+        ] {
+            if !path.exists() {
+                write_atomic(&path, b"[]")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Older versions stored amounts as floats. Reading them rounds to whole cents, so rewrite
+    /// the file once to make that rounding permanent.
+    fn migrate_float_amounts<T: DeserializeOwned + Serialize>(&self, file: &str) -> Result<()> {
+        let path = self.base.join(file);
+        if !path.exists() {
+            return Ok(());
+        }
+        let raw: Vec<serde_json::Value> = self.read(file)?;
+        if raw.iter().any(|v| v["amount"].is_number()) {
+            let items: Vec<T> = self.read(file)?;
+            self.write(file, &items)?;
+        }
+        Ok(())
+    }
+
+    /// Records written before ids existed get one, unique across all of `files`.
+    fn assign_missing_ids<T: DeserializeOwned + Serialize + Record>(
+        &self,
+        files: &[String],
+        collection: &str,
+    ) -> Result<()> {
+        let mut contents: Vec<Vec<T>> = vec![];
+        for file in files {
+            contents.push(self.read_or_empty(file)?);
+        }
+        let mut last_ids = self.last_ids()?;
+        let last_id = last_ids.get(collection).copied().unwrap_or(0);
+        let mut id = next_id(contents.iter().flatten(), last_id);
+        for (file, items) in files.iter().zip(contents.iter_mut()) {
+            let mut changed = false;
+            for item in items.iter_mut().filter(|i| i.id() == 0) {
+                item.set_id(id);
+                id += 1;
+                changed = true;
+            }
+            if changed {
+                self.write(file, items)?;
+                last_ids.insert(collection.to_string(), id - 1);
+            }
+        }
+        if last_ids.get(collection).copied().unwrap_or(0) != last_id {
+            self.write_last_ids(&last_ids)?;
+        }
+        Ok(())
+    }
+
+    /// The highest id ever handed out per collection. Data directories from before this was
+    /// tracked have none, their highest id in use is the best guess there is.
+    pub fn last_ids(&self) -> Result<BTreeMap<String, u64>> {
+        let path = self.base.join(DB_FILE_IDS);
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        Ok(serde_json::from_reader(&File::open(path)?)?)
+    }
+
+    fn write_last_ids(&self, last_ids: &BTreeMap<String, u64>) -> Result<()> {
+        write_atomic(
+            &self.base.join(DB_FILE_IDS),
+            &serde_json::to_vec_pretty(last_ids)?,
+        )
+    }
+
+    /// The active bookings followed by all archive files.
+    fn booking_files(&self) -> Result<Vec<String>> {
+        let mut files = vec![DB_FILE_BOOKINGS.to_string()];
+        files.extend(self.archive_files()?);
+        Ok(files)
+    }
+
+    /// The single archive file followed by the yearly ones, oldest first.
+    fn archive_files(&self) -> Result<Vec<String>> {
+        let mut yearly = vec![];
+        for entry in fs::read_dir(&self.base)? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            let is_year = name
+                .strip_prefix(DB_FILE_BOOKINGS_ARCHIVE_YEAR_PREFIX)
+                .and_then(|rest| rest.strip_suffix(".json"))
+                .is_some_and(|year| year.parse::<i32>().is_ok());
+            if is_year {
+                yearly.push(name);
+            }
+        }
+        yearly.sort();
+        let mut files = vec![DB_FILE_BOOKINGS_ARCHIVE.to_string()];
+        files.extend(yearly);
+        Ok(files)
+    }
+
+    fn read_or_empty<T: DeserializeOwned>(&self, file: &str) -> Result<Vec<T>> {
+        if self.base.join(file).exists() {
+            self.read(file)
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    fn read<T: DeserializeOwned>(&self, file: &str) -> Result<Vec<T>> {
+        // Read-only mode can't create the files of a new data directory
+        if self.read_only && !self.base.join(file).exists() {
+            return Ok(vec![]);
+        }
+        let items: Vec<T> = serde_json::from_reader(&File::open(self.base.join(file))?)?;
+        Ok(items)
+    }
+
+    /// Appends `item` to `file`, without an id it gets one that was never used in `collection`.
+    /// An explicit id must not be in use in any of `files`.
+    fn insert<T: DeserializeOwned + Serialize + Record>(
+        &self,
+        files: &[String],
+        file: &str,
+        collection: &str,
+        entity: &str,
+        mut item: T,
+    ) -> Result<u64> {
+        let _lock = self.lock()?;
+        let mut all: Vec<T> = vec![];
+        for f in files {
+            all.extend(self.read_or_empty::<T>(f)?);
+        }
+        let mut last_ids = self.last_ids()?;
+        let last_id = last_ids.get(collection).copied().unwrap_or(0);
+        let id = assign_id(&mut item, all.iter(), last_id, entity)?;
+        let mut items: Vec<T> = self.read(file)?;
+        items.push(item);
+        self.write(file, &items)?;
+        if id > last_id {
+            last_ids.insert(collection.to_string(), id);
+            self.write_last_ids(&last_ids)?;
+        }
+        Ok(id)
+    }
+
+    /// Applies `change` to the first of `files` holding the record with `id` and writes it back.
+    fn modify<T: DeserializeOwned + Serialize + Record, R>(
+        &self,
+        files: &[String],
+        id: u64,
+        entity: &str,
+        change: impl FnOnce(&mut Vec<T>, usize) -> R,
+    ) -> Result<R> {
+        let _lock = self.lock()?;
+        for file in files {
+            let mut items: Vec<T> = self.read_or_empty(file)?;
+            if let Some(i) = items.iter().position(|item| item.id() == id) {
+                let result = change(&mut items, i);
+                self.write(file, &items)?;
+                return Ok(result);
+            }
+        }
+        Err(not_found(entity, id))
+    }
+
+    fn write<T: Serialize>(&self, file: &str, items: &[T]) -> Result<()> {
+        write_atomic(&self.base.join(file), &serde_json::to_vec_pretty(items)?)
+    }
+}
+
+/// Replaces `path` with `contents` without ever leaving a truncated file behind.
+///
+/// The data goes to a temp file in the same directory which is synced and then renamed over the
+/// original, so a crash leaves either the old or the new version. The previous version is kept as
+/// `<file>.bak`.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp_path = sibling_path(path, "tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        writer.write_all(contents)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+    }
+
+    if path.exists() {
+        fs::copy(path, sibling_path(path, "bak"))?;
+    }
+    fs::rename(&tmp_path, path)?;
+
+    // persist the rename itself, only possible on unix
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(extension);
+    path.with_file_name(file_name)
+}
+
+impl Storage for JsonStorage {
+    fn get_expenses(&self) -> Result<Vec<BudgetBooking>> {
+        self.read(DB_FILE_BOOKINGS)
+    }
+
+    fn get_expenses_archive(&self) -> Result<Vec<BudgetBooking>> {
+        let mut bookings = vec![];
+        for file in self.archive_files()? {
+            bookings.extend(self.read_or_empty::<BudgetBooking>(&file)?);
+        }
+        Ok(bookings)
+    }
+
+    fn add_expense(&self, booking: BudgetBooking) -> Result<u64> {
+        self.insert(
+            &self.booking_files()?,
+            DB_FILE_BOOKINGS,
+            "bookings",
+            "booking",
+            booking,
+        )
+    }
+
+    fn update_expense(&self, booking: BudgetBooking) -> Result<()> {
+        self.modify(&self.booking_files()?, booking.id, "booking", |items, i| {
+            items[i] = booking
+        })
+    }
+
+    fn delete_expense(&self, id: u64) -> Result<BudgetBooking> {
+        self.modify(&self.booking_files()?, id, "booking", |items, i| {
+            items.remove(i)
+        })
+    }
+
+    fn archive_expenses_before(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        let _lock = self.lock()?;
+        let (closed, active): (Vec<BudgetBooking>, Vec<BudgetBooking>) = self
+            .read::<BudgetBooking>(DB_FILE_BOOKINGS)?
+            .into_iter()
+            .partition(|b| b.date < cutoff);
+        if closed.is_empty() {
+            return Ok(0);
+        }
+
+        let split_yearly = self.get_setting_archive_split_yearly()?;
+        let mut by_file: BTreeMap<String, Vec<BudgetBooking>> = BTreeMap::new();
+        for b in closed.iter() {
+            let file = if split_yearly {
+                format!(
+                    "{}{}.json",
+                    DB_FILE_BOOKINGS_ARCHIVE_YEAR_PREFIX,
+                    b.local_date().year()
+                )
+            } else {
+                DB_FILE_BOOKINGS_ARCHIVE.to_string()
+            };
+            by_file.entry(file).or_default().push(b.clone());
+        }
+
+        // Archive first: if we crash before the active file is rewritten, the next run finds the
+        // bookings already archived and skips them instead of losing them.
+        for (file, bookings) in by_file {
+            let mut archive: Vec<BudgetBooking> = self.read_or_empty(&file)?;
+            for b in bookings {
+                if !archive.contains(&b) {
+                    archive.push(b);
+                }
+            }
+            self.write(&file, &archive)?;
+        }
+        self.write(DB_FILE_BOOKINGS, &active)?;
+        Ok(closed.len())
+    }
+
+    fn get_period_summaries(&self) -> Result<Vec<PeriodSummary>> {
+        self.read(DB_FILE_PERIOD_SUMMARIES)
+    }
+
+    fn set_period_summary(&self, summary: PeriodSummary) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut summaries: Vec<PeriodSummary> = self.read(DB_FILE_PERIOD_SUMMARIES)?;
+        summaries.retain(|s| s.start != summary.start);
+        summaries.push(summary);
+        summaries.sort_by_key(|s| s.start);
+        self.write(DB_FILE_PERIOD_SUMMARIES, &summaries)
+    }
+
+    fn get_recurring(&self) -> Result<Vec<RecurringBooking>> {
+        self.read(DB_FILE_RECURRING)
+    }
+
+    fn add_recurring(&self, rec: RecurringBooking) -> Result<u64> {
+        let files = [DB_FILE_RECURRING.to_string()];
+        self.insert(
+            &files,
+            DB_FILE_RECURRING,
+            "recurring",
+            "recurring item",
+            rec,
+        )
+    }
+
+    fn update_recurring(&self, rec: RecurringBooking) -> Result<()> {
+        let files = [DB_FILE_RECURRING.to_string()];
+        self.modify(&files, rec.id, "recurring item", |items, i| items[i] = rec)
+    }
+
+    fn delete_recurring(&self, id: u64) -> Result<RecurringBooking> {
+        let files = [DB_FILE_RECURRING.to_string()];
+        self.modify(&files, id, "recurring item", |items, i| items.remove(i))
+    }
+
+    fn save_recurring(&self, recurring: Vec<RecurringBooking>) -> Result<()> {
+        let _lock = self.lock()?;
+        self.write(DB_FILE_RECURRING, &recurring)
+    }
+
+    fn get_categories(&self) -> Result<Vec<Category>> {
+        self.read(DB_FILE_CATEGORY)
+    }
+
+    fn add_category(&self, cat: Category) -> Result<u64> {
+        let _lock = self.lock()?;
+        ensure_unique_token(&cat, self.get_categories()?.iter())?;
+        let files = [DB_FILE_CATEGORY.to_string()];
+        self.insert(&files, DB_FILE_CATEGORY, "categories", "category", cat)
+    }
+
+    fn update_category(&self, cat: Category) -> Result<()> {
+        let _lock = self.lock()?;
+        ensure_unique_token(&cat, self.get_categories()?.iter())?;
+        let files = [DB_FILE_CATEGORY.to_string()];
+        self.modify(&files, cat.id, "category", |items, i| items[i] = cat)
+    }
+
+    fn delete_category(&self, id: u64) -> Result<Category> {
+        let files = [DB_FILE_CATEGORY.to_string()];
+        self.modify(&files, id, "category", |items, i| items.remove(i))
+    }
+
+    fn get_settings(&self) -> Result<Vec<Setting>> {
+        self.read(DB_FILE_SETTINGS)
+    }
+
+    fn set_setting(&self, setting: Setting) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut settings: Vec<Setting> = self.read(DB_FILE_SETTINGS)?;
+        match settings.iter_mut().find(|s| s.key == setting.key) {
+            Some(existing) => existing.value = setting.value,
+            None => settings.push(setting),
+        }
+        self.write(DB_FILE_SETTINGS, &settings)
+    }
+
+    fn get_exchange_rates(&self) -> Result<Vec<ExchangeRate>> {
+        self.read(DB_FILE_EXCHANGE_RATES)
+    }
+
+    fn set_exchange_rate(&self, rate: ExchangeRate) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut rates: Vec<ExchangeRate> = self.read(DB_FILE_EXCHANGE_RATES)?;
+        rates.retain(|r| r.currency != rate.currency);
+        rates.push(rate);
+        self.write(DB_FILE_EXCHANGE_RATES, &rates)
+    }
+
+    fn delete_exchange_rate(&self, currency: &str) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut rates: Vec<ExchangeRate> = self.read(DB_FILE_EXCHANGE_RATES)?;
+        rates.retain(|r| r.currency != currency);
+        self.write(DB_FILE_EXCHANGE_RATES, &rates)
+    }
+
+    fn get_assignments(&self) -> Result<Vec<Assignment>> {
+        self.read_or_empty(DB_FILE_ASSIGNMENTS)
+    }
+
+    fn set_assignment(&self, assignment: Assignment) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut assignments: Vec<Assignment> = self.read_or_empty(DB_FILE_ASSIGNMENTS)?;
+        assignments.retain(|a| {
+            a.period != assignment.period || a.category_token != assignment.category_token
+        });
+        assignments.push(assignment);
+        // Same order as the other backends, by period and category
+        assignments
+            .sort_by(|a, b| (a.period, &a.category_token).cmp(&(b.period, &b.category_token)));
+        self.write(DB_FILE_ASSIGNMENTS, &assignments)
+    }
+
+    fn delete_assignment(&self, period: NaiveDate, category_token: &str) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut assignments: Vec<Assignment> = self.read_or_empty(DB_FILE_ASSIGNMENTS)?;
+        assignments.retain(|a| a.period != period || a.category_token != category_token);
+        self.write(DB_FILE_ASSIGNMENTS, &assignments)
+    }
+
+    fn get_journal(&self) -> Result<Journal> {
+        let path = self.base.join(DB_FILE_JOURNAL);
+        if !path.exists() {
+            return Ok(Journal::default());
+        }
+        Ok(serde_json::from_reader(&File::open(path)?)?)
+    }
+
+    fn save_journal(&self, journal: &Journal) -> Result<()> {
+        let _lock = self.lock()?;
+        write_atomic(
+            &self.base.join(DB_FILE_JOURNAL),
+            &serde_json::to_vec_pretty(journal)?,
+        )
+    }
+
+    fn with_lock(&self, f: &mut dyn FnMut() -> Result<()>) -> Result<()> {
+        let _lock = self.lock()?;
+        f()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_ids_for_legacy_records_and_crud() {
+        let dir = TempDir::new("ids");
+        let booking =
+            r#"{"name":"Old","kind":"Expense","category_token":"f","amount":"1.00","date":0}"#;
+        fs::write(dir.join(DB_FILE_BOOKINGS), format!("[{}]", booking)).unwrap();
+        fs::write(dir.join(DB_FILE_BOOKINGS_ARCHIVE), format!("[{}]", booking)).unwrap();
+
+        let storage = JsonStorage::new(&dir).unwrap();
+        assert_eq!(storage.get_expenses().unwrap()[0].id, 1);
+        assert_eq!(storage.get_expenses_archive().unwrap()[0].id, 2);
+
+        let mut new = BudgetBooking::new(
+            "New",
+            crate::model::BookingType::Expense,
+            "f",
+            "2".parse().unwrap(),
+        );
+        assert_eq!(storage.add_expense(new.clone()).unwrap(), 3);
+
+        // archived bookings can be changed as well
+        new.id = 2;
+        storage.update_expense(new).unwrap();
+        assert_eq!(storage.get_expenses_archive().unwrap()[0].name, "New");
+        assert_eq!(storage.delete_expense(1).unwrap().name, "Old");
+        assert!(storage.delete_expense(1).is_err());
+        assert_eq!(storage.get_expenses().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_ids_are_never_reused() {
+        let dir = TempDir::new("reuse");
+        let storage = JsonStorage::new(&dir).unwrap();
+        storage.add_category(Category::new("Food", "f")).unwrap();
+        assert_eq!(storage.add_category(Category::new("Car", "c")).unwrap(), 2);
+        let car = storage.delete_category(2).unwrap();
+        assert_eq!(storage.add_category(Category::new("Home", "h")).unwrap(), 3);
+
+        // the highest id survives reopening the directory
+        let storage = JsonStorage::new(&dir).unwrap();
+        storage.delete_category(3).unwrap();
+        assert_eq!(storage.add_category(Category::new("Pets", "p")).unwrap(), 4);
+
+        let mut clash = Category::new("Fun", "u");
+        clash.id = 1;
+        assert!(storage.add_category(clash).is_err());
+        assert_eq!(storage.add_category(car).unwrap(), 2);
+        assert_eq!(storage.get_categories().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_category_tokens_are_unique() {
+        let dir = TempDir::new("tokens");
+        let storage = JsonStorage::new(&dir).unwrap();
+        storage.add_category(Category::new("Food", "f")).unwrap();
+        assert!(storage.add_category(Category::new("Fuel", "f")).is_err());
+
+        let id = storage.add_category(Category::new("Car", "c")).unwrap();
+        let mut car = Category::new("Fuel", "f");
+        car.id = id;
+        assert!(storage.update_category(car.clone()).is_err());
+        car.token = "c".to_string();
+        storage.update_category(car).unwrap();
+        assert_eq!(storage.get_categories().unwrap()[1].name, "Fuel");
+    }
+
+    #[test]
+    fn test_set_setting() {
+        let dir = TempDir::new("settings");
+        let storage = JsonStorage::new(&dir).unwrap();
+        for currency in ["EUR", "CHF"] {
+            storage
+                .set_setting(Setting {
+                    key: "Base_Currency".to_string(),
+                    value: currency.to_string(),
+                })
+                .unwrap();
+        }
+        let storage = JsonStorage::new(&dir).unwrap();
+        assert_eq!(storage.get_settings().unwrap().len(), 1);
+        assert_eq!(storage.get_setting_base_currency().unwrap(), "CHF");
+    }
+
+    #[test]
+    fn test_read_only_new_data_dir() {
+        let dir = TempDir::new("read-only");
+        let storage = JsonStorage::new_read_only(&dir);
+        assert!(storage.get_expenses().unwrap().is_empty());
+        assert!(storage.get_categories().unwrap().is_empty());
+        assert_eq!(storage.get_setting_period_start_day().unwrap(), 1);
+        assert!(storage.add_category(Category::new("Food", "f")).is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_backup() {
+        let dir = TempDir::new("atomic");
+        let path = dir.join("data.json");
+
+        write_atomic(&path, b"[1]").unwrap();
+        assert!(!dir.join("data.json.bak").exists());
+        write_atomic(&path, b"[1,2]").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[1,2]");
+        assert_eq!(
+            fs::read_to_string(dir.join("data.json.bak")).unwrap(),
+            "[1]"
+        );
+        assert!(!dir.join("data.json.tmp").exists());
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::{anyhow, Result};
+use fs2::FileExt;
+use std::{
+    fs::{File, OpenOptions},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+const LOCK_FILE: &str = "bum.lock";
+const LOCK_TIMEOUT: Duration = Duration::from_secs(2);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Advisory lock on the data directory, released when dropped.
+///
+/// Only writers take it, so other instances can keep reading while it is held.
+pub struct DirLock {
+    file: File,
+}
+
+impl DirLock {
+    pub fn acquire(base: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(base.join(LOCK_FILE))?;
+
+        let started = Instant::now();
+        while let Err(e) = file.try_lock_exclusive() {
+            if e.kind() != fs2::lock_contended_error().kind() {
+                return Err(e.into());
+            }
+            if started.elapsed() > LOCK_TIMEOUT {
+                return Err(anyhow!(
+                    "Data directory is locked by another bum instance, try again later"
+                ));
+            }
+            thread::sleep(LOCK_RETRY_INTERVAL);
+        }
+        Ok(DirLock { file })
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let dir = TempDir::new("lock");
+
+        let lock = DirLock::acquire(&dir).unwrap();
+        assert!(DirLock::acquire(&dir).is_err());
+        drop(lock);
+        assert!(DirLock::acquire(&dir).is_ok());
+    }
+}
@@ -0,0 +1,240 @@
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::{cell::RefCell, collections::BTreeMap};
+
+use super::{assign_id, ensure_unique_token, not_found, Storage};
+use crate::journal::Journal;
+use crate::model::{
+    Assignment, BudgetBooking, Category, ExchangeRate, PeriodSummary, Record, RecurringBooking,
+    Setting,
+};
+
+/// Keeps everything in memory, used to test commands and calculations without touching disk.
+#[derive(Default)]
+pub struct MemoryStorage {
+    pub bookings: RefCell<Vec<BudgetBooking>>,
+    pub bookings_archive: RefCell<Vec<BudgetBooking>>,
+    pub recurring: RefCell<Vec<RecurringBooking>>,
+    pub categories: RefCell<Vec<Category>>,
+    pub settings: RefCell<Vec<Setting>>,
+    pub exchange_rates: RefCell<Vec<ExchangeRate>>,
+    pub period_summaries: RefCell<Vec<PeriodSummary>>,
+    pub assignments: RefCell<Vec<Assignment>>,
+    pub journal: RefCell<Journal>,
+    /// The highest id ever handed out per collection.
+    pub last_ids: RefCell<BTreeMap<&'static str, u64>>,
+}
+
+impl Storage for MemoryStorage {
+    fn get_expenses(&self) -> Result<Vec<BudgetBooking>> {
+        Ok(self.bookings.borrow().clone())
+    }
+
+    fn get_expenses_archive(&self) -> Result<Vec<BudgetBooking>> {
+        Ok(self.bookings_archive.borrow().clone())
+    }
+
+    fn add_expense(&self, mut booking: BudgetBooking) -> Result<u64> {
+        let mut last_ids = self.last_ids.borrow_mut();
+        let last_id = last_ids.entry("bookings").or_default();
+        let id = assign_id(
+            &mut booking,
+            self.bookings
+                .borrow()
+                .iter()
+                .chain(self.bookings_archive.borrow().iter()),
+            *last_id,
+            "booking",
+        )?;
+        *last_id = id.max(*last_id);
+        self.bookings.borrow_mut().push(booking);
+        Ok(id)
+    }
+
+    fn update_expense(&self, booking: BudgetBooking) -> Result<()> {
+        let in_archive = self
+            .bookings_archive
+            .borrow()
+            .iter()
+            .any(|b| b.id == booking.id);
+        if in_archive {
+            update(&self.bookings_archive, booking, "booking")
+        } else {
+            update(&self.bookings, booking, "booking")
+        }
+    }
+
+    fn delete_expense(&self, id: u64) -> Result<BudgetBooking> {
+        delete(&self.bookings, id, "booking")
+            .or_else(|_| delete(&self.bookings_archive, id, "booking"))
+    }
+
+    fn archive_expenses_before(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        let (closed, active) = self
+            .bookings
+            .take()
+            .into_iter()
+            .partition::<Vec<_>, _>(|b| b.date < cutoff);
+        *self.bookings.borrow_mut() = active;
+        let count = closed.len();
+        self.bookings_archive.borrow_mut().extend(closed);
+        Ok(count)
+    }
+
+    fn get_period_summaries(&self) -> Result<Vec<PeriodSummary>> {
+        Ok(self.period_summaries.borrow().clone())
+    }
+
+    fn set_period_summary(&self, summary: PeriodSummary) -> Result<()> {
+        let mut summaries = self.period_summaries.borrow_mut();
+        summaries.retain(|s| s.start != summary.start);
+        summaries.push(summary);
+        Ok(())
+    }
+
+    fn get_recurring(&self) -> Result<Vec<RecurringBooking>> {
+        Ok(self.recurring.borrow().clone())
+    }
+
+    fn add_recurring(&self, rec: RecurringBooking) -> Result<u64> {
+        add(
+            &self.recurring,
+            rec,
+            &self.last_ids,
+            "recurring",
+            "recurring item",
+        )
+    }
+
+    fn update_recurring(&self, rec: RecurringBooking) -> Result<()> {
+        update(&self.recurring, rec, "recurring item")
+    }
+
+    fn delete_recurring(&self, id: u64) -> Result<RecurringBooking> {
+        delete(&self.recurring, id, "recurring item")
+    }
+
+    fn save_recurring(&self, recurring: Vec<RecurringBooking>) -> Result<()> {
+        *self.recurring.borrow_mut() = recurring;
+        Ok(())
+    }
+
+    fn get_categories(&self) -> Result<Vec<Category>> {
+        Ok(self.categories.borrow().clone())
+    }
+
+    fn add_category(&self, cat: Category) -> Result<u64> {
+        ensure_unique_token(&cat, self.categories.borrow().iter())?;
+        add(
+            &self.categories,
+            cat,
+            &self.last_ids,
+            "categories",
+            "category",
+        )
+    }
+
+    fn update_category(&self, cat: Category) -> Result<()> {
+        ensure_unique_token(&cat, self.categories.borrow().iter())?;
+        update(&self.categories, cat, "category")
+    }
+
+    fn delete_category(&self, id: u64) -> Result<Category> {
+        delete(&self.categories, id, "category")
+    }
+
+    fn get_settings(&self) -> Result<Vec<Setting>> {
+        Ok(self.settings.borrow().clone())
+    }
+
+    fn set_setting(&self, setting: Setting) -> Result<()> {
+        let mut settings = self.settings.borrow_mut();
+        match settings.iter_mut().find(|s| s.key == setting.key) {
+            Some(existing) => existing.value = setting.value,
+            None => settings.push(setting),
+        }
+        Ok(())
+    }
+
+    fn get_exchange_rates(&self) -> Result<Vec<ExchangeRate>> {
+        Ok(self.exchange_rates.borrow().clone())
+    }
+
+    fn set_exchange_rate(&self, rate: ExchangeRate) -> Result<()> {
+        let mut rates = self.exchange_rates.borrow_mut();
+        rates.retain(|r| r.currency != rate.currency);
+        rates.push(rate);
+        Ok(())
+    }
+
+    fn delete_exchange_rate(&self, currency: &str) -> Result<()> {
+        self.exchange_rates
+            .borrow_mut()
+            .retain(|r| r.currency != currency);
+        Ok(())
+    }
+
+    fn get_assignments(&self) -> Result<Vec<Assignment>> {
+        Ok(self.assignments.borrow().clone())
+    }
+
+    fn set_assignment(&self, assignment: Assignment) -> Result<()> {
+        self.delete_assignment(assignment.period, &assignment.category_token)?;
+        let mut assignments = self.assignments.borrow_mut();
+        assignments.push(assignment);
+        assignments
+            .sort_by(|a, b| (a.period, &a.category_token).cmp(&(b.period, &b.category_token)));
+        Ok(())
+    }
+
+    fn delete_assignment(&self, period: NaiveDate, category_token: &str) -> Result<()> {
+        self.assignments
+            .borrow_mut()
+            .retain(|a| a.period != period || a.category_token != category_token);
+        Ok(())
+    }
+
+    fn get_journal(&self) -> Result<Journal> {
+        Ok(self.journal.borrow().clone())
+    }
+
+    fn save_journal(&self, journal: &Journal) -> Result<()> {
+        *self.journal.borrow_mut() = journal.clone();
+        Ok(())
+    }
+}
+
+fn add<T: Record>(
+    list: &RefCell<Vec<T>>,
+    mut item: T,
+    last_ids: &RefCell<BTreeMap<&'static str, u64>>,
+    collection: &'static str,
+    entity: &str,
+) -> Result<u64> {
+    let mut list = list.borrow_mut();
+    let mut last_ids = last_ids.borrow_mut();
+    let last_id = last_ids.entry(collection).or_default();
+    let id = assign_id(&mut item, list.iter(), *last_id, entity)?;
+    *last_id = id.max(*last_id);
+    list.push(item);
+    Ok(id)
+}
+
+fn update<T: Record>(list: &RefCell<Vec<T>>, item: T, entity: &str) -> Result<()> {
+    let mut list = list.borrow_mut();
+    match list.iter_mut().find(|i| i.id() == item.id()) {
+        Some(existing) => {
+            *existing = item;
+            Ok(())
+        }
+        None => Err(not_found(entity, item.id())),
+    }
+}
+
+fn delete<T: Record>(list: &RefCell<Vec<T>>, id: u64, entity: &str) -> Result<T> {
+    let mut list = list.borrow_mut();
+    match list.iter().position(|i| i.id() == id) {
+        Some(i) => Ok(list.remove(i)),
+        None => Err(not_found(entity, id)),
+    }
+}
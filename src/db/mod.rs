@@ -0,0 +1,300 @@
+use anyhow::{anyhow, Result};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::journal::Journal;
+use crate::model::{
+    Assignment, BudgetBooking, Category, ExchangeRate, PeriodSummary, Record, RecurringBooking,
+    Setting,
+};
+
+mod json;
+mod lock;
+#[cfg(test)]
+mod memory;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use json::JsonStorage;
+#[cfg(test)]
+pub use memory::MemoryStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+/// Database file of a data directory migrated to SQLite.
+const DB_FILE_SQLITE: &str = "bum.sqlite";
+const DATA_DIR_ENV: &str = "BUM_DATA_DIR";
+const DATA_DIR_NAME: &str = "bum";
+
+/// Resolves the directory holding the data files and creates it if needed.
+///
+/// Order: `--data-dir` flag, `$BUM_DATA_DIR`, `$XDG_DATA_HOME/bum`, `~/.local/share/bum`.
+pub fn resolve_data_dir(cli_dir: Option<PathBuf>) -> Result<PathBuf> {
+    let dir = match cli_dir {
+        Some(dir) => dir,
+        None => default_data_dir(
+            env::var_os(DATA_DIR_ENV).map(PathBuf::from),
+            env::var_os("XDG_DATA_HOME").map(PathBuf::from),
+            env::var_os("HOME").map(PathBuf::from),
+        )?,
+    };
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Opens the storage backend in use for the data directory.
+///
+/// Once a data directory has been migrated to SQLite the database takes precedence over the JSON files.
+pub fn open(base: &Path, read_only: bool) -> Result<Box<dyn Storage>> {
+    if base.join(DB_FILE_SQLITE).exists() {
+        #[cfg(feature = "sqlite")]
+        return Ok(Box::new(SqliteStorage::open(base, read_only)?));
+        // The JSON files left behind are outdated
+        #[cfg(not(feature = "sqlite"))]
+        return Err(anyhow!(
+            "{} was migrated to SQLite, rebuild bum with `--features sqlite`",
+            base.display()
+        ));
+    }
+    if read_only {
+        return Ok(Box::new(JsonStorage::new_read_only(base)));
+    }
+    Ok(Box::new(JsonStorage::new(base)?))
+}
+
+/// Like [`open`], but opens an SQLite database that can't be upgraded until categories sharing a
+/// token are repaired.
+pub fn open_for_check(base: &Path, read_only: bool) -> Result<Box<dyn Storage>> {
+    #[cfg(feature = "sqlite")]
+    if !read_only && base.join(DB_FILE_SQLITE).exists() {
+        return Ok(Box::new(SqliteStorage::open_for_check(base)?));
+    }
+    open(base, read_only)
+}
+
+/// Imports the JSON files of the data directory into a new SQLite database.
+#[cfg(feature = "sqlite")]
+pub fn migrate_to_sqlite(base: &Path) -> Result<PathBuf> {
+    let db_path = base.join(DB_FILE_SQLITE);
+    if db_path.exists() {
+        return Err(anyhow!("{} already exists", db_path.display()));
+    }
+    let json = JsonStorage::new(base)?;
+    let mut sqlite = SqliteStorage::open(base, false)?;
+    if let Err(e) = sqlite
+        .import(&json)
+        .and_then(|_| sqlite.continue_ids(&json.last_ids()?))
+    {
+        drop(sqlite);
+        fs::remove_file(&db_path)?;
+        return Err(e);
+    }
+    Ok(db_path)
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn migrate_to_sqlite(_base: &Path) -> Result<PathBuf> {
+    Err(anyhow!(
+        "bum was built without SQLite support, rebuild with `--features sqlite`"
+    ))
+}
+
+fn not_found(entity: &str, id: u64) -> anyhow::Error {
+    anyhow!("No {} with id {}", entity, id)
+}
+
+/// The id for a new record, one above the highest one in use and above `last_id`, the highest
+/// one ever handed out. Ids of deleted records are never given out again, so undoing the delete
+/// can't clash with a newer record.
+fn next_id<'a, T: Record + 'a>(items: impl Iterator<Item = &'a T>, last_id: u64) -> u64 {
+    items.map(|i| i.id()).max().unwrap_or(0).max(last_id) + 1
+}
+
+/// Gives `item` the next id unless it already has one, which then must not be in use. Returns
+/// its id.
+fn assign_id<'a, T: Record + 'a>(
+    item: &mut T,
+    mut existing: impl Iterator<Item = &'a T>,
+    last_id: u64,
+    entity: &str,
+) -> Result<u64> {
+    if item.id() == 0 {
+        item.set_id(next_id(existing, last_id));
+    } else if existing.any(|i| i.id() == item.id()) {
+        return Err(anyhow!("A {} with id {} already exists", entity, item.id()));
+    }
+    Ok(item.id())
+}
+
+/// Fails if a category other than `cat` already has its token.
+fn ensure_unique_token<'a>(
+    cat: &Category,
+    mut existing: impl Iterator<Item = &'a Category>,
+) -> Result<()> {
+    if existing.any(|c| c.token == cat.token && c.id != cat.id) {
+        return Err(anyhow!(
+            "A category with token '{}' already exists",
+            cat.token
+        ));
+    }
+    Ok(())
+}
+
+fn default_data_dir(
+    env_dir: Option<PathBuf>,
+    xdg_data_home: Option<PathBuf>,
+    home: Option<PathBuf>,
+) -> Result<PathBuf> {
+    if let Some(dir) = env_dir.filter(|d| !d.as_os_str().is_empty()) {
+        return Ok(dir);
+    }
+    // The XDG spec says relative paths are invalid and should be ignored
+    if let Some(xdg) = xdg_data_home.filter(|d| d.is_absolute()) {
+        return Ok(xdg.join(DATA_DIR_NAME));
+    }
+    match home {
+        Some(home) => Ok(home.join(".local/share").join(DATA_DIR_NAME)),
+        None => Err(anyhow!(
+            "Unable to determine data directory, use --data-dir or set {}",
+            DATA_DIR_ENV
+        )),
+    }
+}
+
+/// Persistence for everything bum keeps between runs.
+pub trait Storage {
+    fn get_expenses(&self) -> Result<Vec<BudgetBooking>>;
+    fn get_expenses_archive(&self) -> Result<Vec<BudgetBooking>>;
+    /// Stores a new booking and returns its id. Only bookings without one get the next free id,
+    /// so a deleted booking can be restored as it was.
+    fn add_expense(&self, booking: BudgetBooking) -> Result<u64>;
+    /// Replaces the active or archived booking with the same id.
+    fn update_expense(&self, booking: BudgetBooking) -> Result<()>;
+    /// Removes the active or archived booking with `id` and returns it.
+    fn delete_expense(&self, id: u64) -> Result<BudgetBooking>;
+    /// Moves every active booking dated before `cutoff` into the archive.
+    fn archive_expenses_before(&self, cutoff: DateTime<Utc>) -> Result<usize>;
+
+    fn get_period_summaries(&self) -> Result<Vec<PeriodSummary>>;
+    /// Adds the summary or replaces the existing one for the same period.
+    fn set_period_summary(&self, summary: PeriodSummary) -> Result<()>;
+
+    fn get_recurring(&self) -> Result<Vec<RecurringBooking>>;
+    fn add_recurring(&self, rec: RecurringBooking) -> Result<u64>;
+    fn update_recurring(&self, rec: RecurringBooking) -> Result<()>;
+    fn delete_recurring(&self, id: u64) -> Result<RecurringBooking>;
+    /// Replaces all recurring items, e.g. after the scheduler advanced their due dates.
+    fn save_recurring(&self, recurring: Vec<RecurringBooking>) -> Result<()>;
+
+    fn get_categories(&self) -> Result<Vec<Category>>;
+    /// Adding or updating a category fails if another one already has its token.
+    fn add_category(&self, cat: Category) -> Result<u64>;
+    fn update_category(&self, cat: Category) -> Result<()>;
+    fn delete_category(&self, id: u64) -> Result<Category>;
+
+    fn get_settings(&self) -> Result<Vec<Setting>>;
+    /// Adds the setting or replaces the value of the existing one with the same key.
+    fn set_setting(&self, setting: Setting) -> Result<()>;
+
+    fn get_exchange_rates(&self) -> Result<Vec<ExchangeRate>>;
+    /// Adds the rate or replaces the existing one for the same currency.
+    fn set_exchange_rate(&self, rate: ExchangeRate) -> Result<()>;
+    fn delete_exchange_rate(&self, currency: &str) -> Result<()>;
+
+    fn get_assignments(&self) -> Result<Vec<Assignment>>;
+    /// Adds the assignment or replaces the existing one for the same period and category.
+    fn set_assignment(&self, assignment: Assignment) -> Result<()>;
+    fn delete_assignment(&self, period: NaiveDate, category_token: &str) -> Result<()>;
+
+    /// The undo/redo history, empty if nothing was recorded yet.
+    fn get_journal(&self) -> Result<Journal>;
+    fn save_journal(&self, journal: &Journal) -> Result<()>;
+
+    /// Runs `f` without other instances writing in between, for changes that read data before
+    /// writing it back. Storage calls inside `f` share the lock.
+    fn with_lock(&self, f: &mut dyn FnMut() -> Result<()>) -> Result<()> {
+        f()
+    }
+
+    fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .get_settings()?
+            .into_iter()
+            .find(|s| s.key == key)
+            .map(|s| s.value))
+    }
+
+    fn get_setting_currency_symbol(&self) -> Result<String> {
+        Ok(self.get_setting("Currency_Symbol")?.unwrap_or_default())
+    }
+
+    fn get_setting_base_currency(&self) -> Result<String> {
+        Ok(self.get_setting("Base_Currency")?.unwrap_or_default())
+    }
+
+    /// Whether archived bookings go into one file per year instead of a single file. Only the JSON
+    /// backend has files to split.
+    fn get_setting_archive_split_yearly(&self) -> Result<bool> {
+        Ok(matches!(
+            self.get_setting("Archive_Split_Yearly")?.as_deref(),
+            Some("true" | "yes" | "1")
+        ))
+    }
+
+    /// Whether income is assigned to category envelopes instead of the single budget.
+    fn get_setting_envelope_mode(&self) -> Result<bool> {
+        Ok(matches!(
+            self.get_setting("Envelope_Mode")?.as_deref(),
+            Some("true" | "yes" | "1")
+        ))
+    }
+
+    /// Day of the month budget periods start on, 1 unless configured.
+    fn get_setting_period_start_day(&self) -> Result<u32> {
+        match self.get_setting("Period_Start_Day")? {
+            Some(day) => match day.trim().parse::<u32>() {
+                Ok(day) if (1..=31).contains(&day) => Ok(day),
+                _ => Err(anyhow!("Period_Start_Day must be between 1 and 31")),
+            },
+            None => Ok(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_dir_resolution() {
+        let env_dir = Some(PathBuf::from("/tmp/bum-env"));
+        let xdg = Some(PathBuf::from("/home/u/.xdg"));
+        let home = Some(PathBuf::from("/home/u"));
+
+        let dir = default_data_dir(env_dir, xdg.clone(), home.clone()).unwrap();
+        assert_eq!(dir, PathBuf::from("/tmp/bum-env"));
+
+        let dir = default_data_dir(None, xdg, home.clone()).unwrap();
+        assert_eq!(dir, PathBuf::from("/home/u/.xdg/bum"));
+
+        let dir = default_data_dir(Some(PathBuf::new()), Some("rel".into()), home).unwrap();
+        assert_eq!(dir, PathBuf::from("/home/u/.local/share/bum"));
+
+        assert!(default_data_dir(None, None, None).is_err());
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    #[test]
+    fn test_migrated_dir_needs_sqlite() {
+        use crate::testutil::TempDir;
+        let dir = TempDir::new("migrated");
+        fs::write(dir.join(DB_FILE_SQLITE), b"").unwrap();
+        let error = open(&dir, false).err().unwrap().to_string();
+        assert!(error.contains("--features sqlite"), "{}", error);
+        assert!(!dir.join("data_bookings.json").exists());
+    }
+}
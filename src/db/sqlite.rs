@@ -0,0 +1,1000 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+use std::{collections::BTreeMap, path::Path};
+
+use super::{ensure_unique_token, not_found, Storage, DB_FILE_SQLITE};
+use crate::journal::Journal;
+use crate::model::{
+    Assignment, BookingType, BudgetBooking, Category, ExchangeRate, PeriodSummary, Recurrence,
+    RecurringBooking, RecurringType, Setting,
+};
+
+/// Schema migrations, applied in order. The index + 1 is stored in `PRAGMA user_version`,
+/// so existing entries must never be changed, only new ones appended.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema mirroring the JSON files
+    "CREATE TABLE bookings (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        category_token TEXT NOT NULL,
+        amount REAL NOT NULL,
+        date INTEGER NOT NULL,
+        archived INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE recurring (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        category_token TEXT NOT NULL,
+        amount REAL NOT NULL,
+        rate_type TEXT NOT NULL
+    );
+    CREATE TABLE categories (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        token TEXT NOT NULL,
+        name TEXT NOT NULL
+    );
+    CREATE TABLE settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    // 2: amounts as integer cents instead of floats
+    "ALTER TABLE bookings ADD COLUMN amount_minor INTEGER NOT NULL DEFAULT 0;
+    UPDATE bookings SET amount_minor = CAST(ROUND(amount * 100) AS INTEGER);
+    ALTER TABLE bookings DROP COLUMN amount;
+    ALTER TABLE bookings RENAME COLUMN amount_minor TO amount;
+    ALTER TABLE recurring ADD COLUMN amount_minor INTEGER NOT NULL DEFAULT 0;
+    UPDATE recurring SET amount_minor = CAST(ROUND(amount * 100) AS INTEGER);
+    ALTER TABLE recurring DROP COLUMN amount;
+    ALTER TABLE recurring RENAME COLUMN amount_minor TO amount;",
+    // 3: per booking currency and exchange rates
+    "ALTER TABLE bookings ADD COLUMN currency TEXT NOT NULL DEFAULT '';
+    ALTER TABLE recurring ADD COLUMN currency TEXT NOT NULL DEFAULT '';
+    CREATE TABLE exchange_rates (
+        currency TEXT PRIMARY KEY,
+        rate REAL NOT NULL
+    );",
+    // 4: totals of archived periods
+    "CREATE TABLE period_summaries (
+        start TEXT PRIMARY KEY,
+        end TEXT NOT NULL,
+        income INTEGER NOT NULL,
+        recurring_expenses INTEGER NOT NULL,
+        budget_spent INTEGER NOT NULL,
+        budget_left INTEGER NOT NULL
+    );",
+    // 5: scheduling of recurring items
+    "ALTER TABLE recurring ADD COLUMN anchor_date TEXT;
+    ALTER TABLE recurring ADD COLUMN next_due TEXT;
+    ALTER TABLE bookings ADD COLUMN recurring INTEGER NOT NULL DEFAULT 0;",
+    // 6: recurrence intervals and ends
+    "ALTER TABLE recurring ADD COLUMN rate_interval INTEGER NOT NULL DEFAULT 1;
+    ALTER TABLE recurring ADD COLUMN until_date TEXT;
+    ALTER TABLE recurring ADD COLUMN occurrences INTEGER;",
+    // 7: undo/redo history, stored as a single JSON document
+    "CREATE TABLE journal (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        data TEXT NOT NULL
+    );",
+    // 8: subcategories
+    "ALTER TABLE categories ADD COLUMN parent TEXT;",
+    // 9: monthly spending limits
+    "ALTER TABLE categories ADD COLUMN spending_limit INTEGER;",
+    // 10: envelope assignments
+    "CREATE TABLE assignments (
+        period TEXT NOT NULL,
+        category_token TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        PRIMARY KEY (period, category_token)
+    );",
+    // 11: the recurring item a booking was booked from
+    "ALTER TABLE bookings ADD COLUMN recurring_id INTEGER;",
+    // 12: unique tokens, categories sharing one have to be repaired first
+    "CREATE UNIQUE INDEX categories_token ON categories (token);",
+];
+
+/// Index of the migration making tokens unique, `bum check --repair` runs on the schema before it.
+const UNIQUE_TOKENS: usize = 11;
+
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    pub fn open(base: &Path, read_only: bool) -> Result<Self> {
+        let path = base.join(DB_FILE_SQLITE);
+        if read_only {
+            let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            let storage = SqliteStorage { conn };
+            // Migrations need write access, so an outdated schema can't be used
+            let version = storage.schema_version()?;
+            if version != MIGRATIONS.len() {
+                return Err(anyhow!(
+                    "Database schema version {} doesn't match this version of bum ({}), open it once without --read-only to migrate it",
+                    version,
+                    MIGRATIONS.len()
+                ));
+            }
+            return Ok(storage);
+        }
+
+        let mut storage = SqliteStorage {
+            conn: Connection::open(path)?,
+        };
+        storage.migrate(false)?;
+        Ok(storage)
+    }
+
+    /// Like [`open`](Self::open), but leaves a database whose categories share a token at the
+    /// schema before tokens became unique, so the duplicates can be repaired.
+    pub fn open_for_check(base: &Path) -> Result<Self> {
+        let mut storage = SqliteStorage {
+            conn: Connection::open(base.join(DB_FILE_SQLITE))?,
+        };
+        storage.migrate(true)?;
+        Ok(storage)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        let mut storage = SqliteStorage {
+            conn: Connection::open_in_memory()?,
+        };
+        storage.migrate(false)?;
+        Ok(storage)
+    }
+
+    pub fn schema_version(&self) -> Result<usize> {
+        let version: i64 = self
+            .conn
+            .query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        Ok(version as usize)
+    }
+
+    /// Applies the missing migrations. With `stop_at_shared_tokens` categories sharing a token
+    /// stop it before the unique index instead of failing.
+    fn migrate(&mut self, stop_at_shared_tokens: bool) -> Result<()> {
+        let version = self.schema_version()?;
+        if version > MIGRATIONS.len() {
+            return Err(anyhow!(
+                "Database schema version {} is newer than this version of bum supports ({})",
+                version,
+                MIGRATIONS.len()
+            ));
+        }
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            if i == UNIQUE_TOKENS {
+                if let Some(token) = self.shared_token()? {
+                    if stop_at_shared_tokens {
+                        return Ok(());
+                    }
+                    return Err(anyhow!(
+                        "Categories share the token '{}', run `bum check --repair` first",
+                        token
+                    ));
+                }
+            }
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    fn shared_token(&self) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT token FROM categories GROUP BY token HAVING COUNT(*) > 1 ORDER BY token",
+                [],
+                |r| r.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Copies everything from another storage. Refuses to run if there is already data.
+    pub fn import(&mut self, from: &dyn Storage) -> Result<()> {
+        let existing: i64 = self.conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM bookings) + (SELECT COUNT(*) FROM recurring)
+                  + (SELECT COUNT(*) FROM categories) + (SELECT COUNT(*) FROM settings)
+                  + (SELECT COUNT(*) FROM exchange_rates)
+                  + (SELECT COUNT(*) FROM period_summaries)
+                  + (SELECT COUNT(*) FROM assignments)",
+            [],
+            |r| r.get(0),
+        )?;
+        if existing > 0 {
+            return Err(anyhow!("Target database already contains data"));
+        }
+        let categories = from.get_categories()?;
+        for (i, c) in categories.iter().enumerate() {
+            if categories[..i].iter().any(|other| other.token == c.token) {
+                return Err(anyhow!(
+                    "Categories share the token '{}', run `bum check --repair` first",
+                    c.token
+                ));
+            }
+        }
+
+        let tx = self.conn.transaction()?;
+        for b in from.get_expenses()? {
+            insert_booking(&tx, &b, false)?;
+        }
+        for b in from.get_expenses_archive()? {
+            insert_booking(&tx, &b, true)?;
+        }
+        for r in from.get_recurring()? {
+            insert_recurring(&tx, &r)?;
+        }
+        for c in categories {
+            insert_category(&tx, &c)?;
+        }
+        for s in from.get_settings()? {
+            tx.execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2)",
+                params![s.key, s.value],
+            )?;
+        }
+        for r in from.get_exchange_rates()? {
+            insert_exchange_rate(&tx, &r)?;
+        }
+        for summary in from.get_period_summaries()? {
+            insert_period_summary(&tx, &summary)?;
+        }
+        for assignment in from.get_assignments()? {
+            insert_assignment(&tx, &assignment)?;
+        }
+        // ids are kept, so the history still refers to the same records
+        tx.execute(
+            "INSERT INTO journal (id, data) VALUES (1, ?1)",
+            [serde_json::to_string(&from.get_journal()?)?],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn query_bookings(&self, archived: bool) -> Result<Vec<BudgetBooking>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, name, kind, category_token, amount, currency, date, recurring, recurring_id
+             FROM bookings WHERE archived = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map([archived], booking_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Deletes the row with `id` from `table` and returns it, mapped by `from_row`.
+    fn delete_row<T>(
+        &self,
+        table: &str,
+        id: u64,
+        entity: &str,
+        from_row: impl FnOnce(&Row) -> rusqlite::Result<T>,
+    ) -> Result<T> {
+        self.savepoint(|| {
+            let item = self
+                .conn
+                .query_row(
+                    &format!("SELECT * FROM {} WHERE id = ?1", table),
+                    [id],
+                    from_row,
+                )
+                .optional()?
+                .ok_or_else(|| not_found(entity, id))?;
+            self.conn
+                .execute(&format!("DELETE FROM {} WHERE id = ?1", table), [id])?;
+            Ok(item)
+        })
+    }
+
+    /// Runs `f` as a unit. Unlike a transaction a savepoint also works inside `with_lock`.
+    fn savepoint<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.conn.execute_batch("SAVEPOINT change")?;
+        match f() {
+            Ok(result) => {
+                self.conn.execute_batch("RELEASE change")?;
+                Ok(result)
+            }
+            Err(e) => {
+                self.conn
+                    .execute_batch("ROLLBACK TO change; RELEASE change")?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Fails unless a row with `id` exists, updates replace rows and must not create new ones.
+    fn ensure_exists(&self, table: &str, id: u64, entity: &str) -> Result<()> {
+        self.conn
+            .query_row(
+                &format!("SELECT id FROM {} WHERE id = ?1", table),
+                [id],
+                |_| Ok(()),
+            )
+            .optional()?
+            .ok_or_else(|| not_found(entity, id))
+    }
+
+    /// A new record may bring its id, e.g. when a delete is undone, but must not replace another.
+    fn ensure_absent(&self, table: &str, id: u64, entity: &str) -> Result<()> {
+        if id != 0 && self.ensure_exists(table, id, entity).is_ok() {
+            return Err(anyhow!("A {} with id {} already exists", entity, id));
+        }
+        Ok(())
+    }
+
+    /// Continues the ids after `last_ids`, the highest ids the imported data ever handed out, so
+    /// ids of records deleted before the import aren't reused either.
+    pub fn continue_ids(&self, last_ids: &BTreeMap<String, u64>) -> Result<()> {
+        for table in ["bookings", "recurring", "categories"] {
+            let Some(&last_id) = last_ids.get(table) else {
+                continue;
+            };
+            let updated = self.conn.execute(
+                "UPDATE sqlite_sequence SET seq = MAX(seq, ?2) WHERE name = ?1",
+                params![table, last_id],
+            )?;
+            if updated == 0 {
+                self.conn.execute(
+                    "INSERT INTO sqlite_sequence (name, seq) VALUES (?1, ?2)",
+                    params![table, last_id],
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn get_expenses(&self) -> Result<Vec<BudgetBooking>> {
+        self.query_bookings(false)
+    }
+
+    fn get_expenses_archive(&self) -> Result<Vec<BudgetBooking>> {
+        self.query_bookings(true)
+    }
+
+    fn add_expense(&self, booking: BudgetBooking) -> Result<u64> {
+        self.ensure_absent("bookings", booking.id, "booking")?;
+        insert_booking(&self.conn, &booking, false)
+    }
+
+    fn update_expense(&self, booking: BudgetBooking) -> Result<()> {
+        let archived: bool = self
+            .conn
+            .query_row(
+                "SELECT archived FROM bookings WHERE id = ?1",
+                [booking.id],
+                |r| r.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| not_found("booking", booking.id))?;
+        insert_booking(&self.conn, &booking, archived)?;
+        Ok(())
+    }
+
+    fn delete_expense(&self, id: u64) -> Result<BudgetBooking> {
+        self.delete_row("bookings", id, "booking", booking_from_row)
+    }
+
+    /// `Archive_Split_Yearly` doesn't apply, the archive is a flag on the bookings table.
+    fn archive_expenses_before(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        Ok(self.conn.execute(
+            "UPDATE bookings SET archived = 1 WHERE archived = 0 AND date < ?1",
+            [cutoff.timestamp()],
+        )?)
+    }
+
+    fn get_period_summaries(&self) -> Result<Vec<PeriodSummary>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT start, end, income, recurring_expenses, budget_spent, budget_left
+             FROM period_summaries ORDER BY start",
+        )?;
+        let rows = stmt.query_map([], |r| {
+            Ok(PeriodSummary {
+                start: date_from_sql(r.get("start")?)?,
+                end: date_from_sql(r.get("end")?)?,
+                income: r.get("income")?,
+                recurring_expenses: r.get("recurring_expenses")?,
+                budget_spent: r.get("budget_spent")?,
+                budget_left: r.get("budget_left")?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn set_period_summary(&self, summary: PeriodSummary) -> Result<()> {
+        insert_period_summary(&self.conn, &summary)
+    }
+
+    fn get_recurring(&self) -> Result<Vec<RecurringBooking>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, name, kind, category_token, amount, currency, rate_type, rate_interval,
+             until_date, occurrences, anchor_date, next_due FROM recurring ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], recurring_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn add_recurring(&self, rec: RecurringBooking) -> Result<u64> {
+        self.ensure_absent("recurring", rec.id, "recurring item")?;
+        insert_recurring(&self.conn, &rec)
+    }
+
+    fn update_recurring(&self, rec: RecurringBooking) -> Result<()> {
+        self.ensure_exists("recurring", rec.id, "recurring item")?;
+        insert_recurring(&self.conn, &rec)?;
+        Ok(())
+    }
+
+    fn delete_recurring(&self, id: u64) -> Result<RecurringBooking> {
+        self.delete_row("recurring", id, "recurring item", recurring_from_row)
+    }
+
+    fn save_recurring(&self, recurring: Vec<RecurringBooking>) -> Result<()> {
+        self.savepoint(|| {
+            self.conn.execute("DELETE FROM recurring", [])?;
+            for rec in &recurring {
+                insert_recurring(&self.conn, rec)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn get_categories(&self) -> Result<Vec<Category>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, token, name, parent, spending_limit FROM categories ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], category_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn add_category(&self, cat: Category) -> Result<u64> {
+        self.ensure_absent("categories", cat.id, "category")?;
+        ensure_unique_token(&cat, self.get_categories()?.iter())?;
+        insert_category(&self.conn, &cat)
+    }
+
+    fn update_category(&self, cat: Category) -> Result<()> {
+        self.ensure_exists("categories", cat.id, "category")?;
+        ensure_unique_token(&cat, self.get_categories()?.iter())?;
+        self.conn.execute(
+            "UPDATE categories SET token = ?2, name = ?3, parent = ?4, spending_limit = ?5
+             WHERE id = ?1",
+            params![cat.id, cat.token, cat.name, cat.parent, cat.limit],
+        )?;
+        Ok(())
+    }
+
+    fn delete_category(&self, id: u64) -> Result<Category> {
+        self.delete_row("categories", id, "category", category_from_row)
+    }
+
+    fn get_settings(&self) -> Result<Vec<Setting>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT key, value FROM settings ORDER BY key")?;
+        let rows = stmt.query_map([], |r| {
+            Ok(Setting {
+                key: r.get("key")?,
+                value: r.get("value")?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn set_setting(&self, setting: Setting) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            params![setting.key, setting.value],
+        )?;
+        Ok(())
+    }
+
+    fn get_exchange_rates(&self) -> Result<Vec<ExchangeRate>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT currency, rate FROM exchange_rates ORDER BY currency")?;
+        let rows = stmt.query_map([], |r| {
+            Ok(ExchangeRate {
+                currency: r.get("currency")?,
+                rate: r.get("rate")?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn set_exchange_rate(&self, rate: ExchangeRate) -> Result<()> {
+        insert_exchange_rate(&self.conn, &rate)
+    }
+
+    fn delete_exchange_rate(&self, currency: &str) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM exchange_rates WHERE currency = ?1", [currency])?;
+        Ok(())
+    }
+
+    fn get_assignments(&self) -> Result<Vec<Assignment>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT period, category_token, amount FROM assignments
+             ORDER BY period, category_token",
+        )?;
+        let rows = stmt.query_map([], |r| {
+            Ok(Assignment {
+                period: date_from_sql(r.get("period")?)?,
+                category_token: r.get("category_token")?,
+                amount: r.get("amount")?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn set_assignment(&self, assignment: Assignment) -> Result<()> {
+        insert_assignment(&self.conn, &assignment)
+    }
+
+    fn delete_assignment(&self, period: NaiveDate, category_token: &str) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM assignments WHERE period = ?1 AND category_token = ?2",
+            params![period.to_string(), category_token],
+        )?;
+        Ok(())
+    }
+
+    fn get_journal(&self) -> Result<Journal> {
+        let data: Option<String> = self
+            .conn
+            .query_row("SELECT data FROM journal WHERE id = 1", [], |r| r.get(0))
+            .optional()?;
+        match data {
+            Some(data) => Ok(serde_json::from_str(&data)?),
+            None => Ok(Journal::default()),
+        }
+    }
+
+    fn save_journal(&self, journal: &Journal) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO journal (id, data) VALUES (1, ?1)",
+            [serde_json::to_string(journal)?],
+        )?;
+        Ok(())
+    }
+
+    fn with_lock(&self, f: &mut dyn FnMut() -> Result<()>) -> Result<()> {
+        // Nested calls run inside the transaction of the outer one
+        if !self.conn.is_autocommit() {
+            return f();
+        }
+        // Takes the write lock right away instead of on the first write
+        self.conn.execute_batch("BEGIN IMMEDIATE")?;
+        match f() {
+            Ok(()) => Ok(self.conn.execute_batch("COMMIT")?),
+            Err(e) => {
+                self.conn.execute_batch("ROLLBACK")?;
+                Err(e)
+            }
+        }
+    }
+}
+
+fn booking_from_row(r: &Row) -> rusqlite::Result<BudgetBooking> {
+    let timestamp: i64 = r.get("date")?;
+    Ok(BudgetBooking {
+        id: r.get("id")?,
+        name: r.get("name")?,
+        kind: booking_type_from_sql(&r.get::<_, String>("kind")?),
+        category_token: r.get("category_token")?,
+        amount: r.get("amount")?,
+        currency: r.get("currency")?,
+        date: Utc.timestamp_opt(timestamp, 0).single().ok_or_else(|| {
+            match r.as_ref().column_index("date") {
+                Ok(index) => rusqlite::Error::IntegralValueOutOfRange(index, timestamp),
+                Err(e) => e,
+            }
+        })?,
+        recurring: r.get("recurring")?,
+        recurring_id: r.get("recurring_id")?,
+    })
+}
+
+fn recurring_from_row(r: &Row) -> rusqlite::Result<RecurringBooking> {
+    Ok(RecurringBooking {
+        id: r.get("id")?,
+        name: r.get("name")?,
+        kind: booking_type_from_sql(&r.get::<_, String>("kind")?),
+        category_token: r.get("category_token")?,
+        amount: r.get("amount")?,
+        currency: r.get("currency")?,
+        recurrence: Recurrence {
+            rate_type: recurring_type_from_sql(&r.get::<_, String>("rate_type")?),
+            interval: r.get("rate_interval")?,
+            until: r
+                .get::<_, Option<String>>("until_date")?
+                .map(date_from_sql)
+                .transpose()?,
+            count: r.get("occurrences")?,
+        },
+        anchor_date: r
+            .get::<_, Option<String>>("anchor_date")?
+            .map(date_from_sql)
+            .transpose()?,
+        next_due: r
+            .get::<_, Option<String>>("next_due")?
+            .map(date_from_sql)
+            .transpose()?,
+    })
+}
+
+fn category_from_row(r: &Row) -> rusqlite::Result<Category> {
+    Ok(Category {
+        id: r.get("id")?,
+        token: r.get("token")?,
+        name: r.get("name")?,
+        parent: r.get("parent")?,
+        limit: r.get("spending_limit")?,
+    })
+}
+
+// The insert_* helpers keep a non-zero id, which replaces the existing row with that id, and
+// otherwise let SQLite assign the next one.
+
+fn insert_booking(conn: &Connection, b: &BudgetBooking, archived: bool) -> Result<u64> {
+    conn.execute(
+        "INSERT OR REPLACE INTO bookings
+         (id, name, kind, category_token, amount, currency, date, archived, recurring,
+          recurring_id)
+         VALUES (NULLIF(?1, 0), ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            b.id,
+            b.name,
+            booking_type_to_sql(&b.kind),
+            b.category_token,
+            b.amount,
+            b.currency,
+            b.date.timestamp(),
+            archived,
+            b.recurring,
+            b.recurring_id
+        ],
+    )?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+fn insert_recurring(conn: &Connection, r: &RecurringBooking) -> Result<u64> {
+    conn.execute(
+        "INSERT OR REPLACE INTO recurring
+         (id, name, kind, category_token, amount, currency, rate_type, rate_interval,
+          until_date, occurrences, anchor_date, next_due)
+         VALUES (NULLIF(?1, 0), ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            r.id,
+            r.name,
+            booking_type_to_sql(&r.kind),
+            r.category_token,
+            r.amount,
+            r.currency,
+            recurring_type_to_sql(&r.recurrence.rate_type),
+            r.recurrence.interval,
+            r.recurrence.until.map(|d| d.to_string()),
+            r.recurrence.count,
+            r.anchor_date.map(|d| d.to_string()),
+            r.next_due.map(|d| d.to_string())
+        ],
+    )?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+fn insert_category(conn: &Connection, c: &Category) -> Result<u64> {
+    conn.execute(
+        "INSERT INTO categories (id, token, name, parent, spending_limit)
+         VALUES (NULLIF(?1, 0), ?2, ?3, ?4, ?5)",
+        params![c.id, c.token, c.name, c.parent, c.limit],
+    )?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+fn insert_exchange_rate(conn: &Connection, r: &ExchangeRate) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO exchange_rates (currency, rate) VALUES (?1, ?2)",
+        params![r.currency, r.rate],
+    )?;
+    Ok(())
+}
+
+fn insert_period_summary(conn: &Connection, s: &PeriodSummary) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO period_summaries
+         (start, end, income, recurring_expenses, budget_spent, budget_left)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            s.start.to_string(),
+            s.end.to_string(),
+            s.income,
+            s.recurring_expenses,
+            s.budget_spent,
+            s.budget_left
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_assignment(conn: &Connection, a: &Assignment) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO assignments (period, category_token, amount) VALUES (?1, ?2, ?3)",
+        params![a.period.to_string(), a.category_token, a.amount],
+    )?;
+    Ok(())
+}
+
+fn date_from_sql(date: String) -> rusqlite::Result<NaiveDate> {
+    date.parse().map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+fn booking_type_to_sql(kind: &BookingType) -> &'static str {
+    match kind {
+        BookingType::Income => "Income",
+        BookingType::Expense => "Expense",
+    }
+}
+
+fn booking_type_from_sql(kind: &str) -> BookingType {
+    match kind {
+        "Income" => BookingType::Income,
+        _ => BookingType::Expense,
+    }
+}
+
+fn recurring_type_to_sql(rate_type: &RecurringType) -> &'static str {
+    match rate_type {
+        RecurringType::Weekly => "Weekly",
+        RecurringType::Monthly => "Monthly",
+        RecurringType::Yearly => "Yearly",
+    }
+}
+
+fn recurring_type_from_sql(rate_type: &str) -> RecurringType {
+    match rate_type {
+        "Weekly" => RecurringType::Weekly,
+        "Yearly" => RecurringType::Yearly,
+        _ => RecurringType::Monthly,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{check, db::MemoryStorage, money::Money, testutil::TempDir};
+
+    #[test]
+    fn test_migrations_and_import() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        assert_eq!(storage.schema_version().unwrap(), MIGRATIONS.len());
+
+        let json = MemoryStorage::default();
+        json.add_category(Category::new("Food", "f")).unwrap();
+        json.add_expense(BudgetBooking::new(
+            "Coffee",
+            BookingType::Expense,
+            "f",
+            Money::from_minor(350),
+        ))
+        .unwrap();
+        json.bookings_archive.borrow_mut().push(BudgetBooking::new(
+            "Old",
+            BookingType::Expense,
+            "f",
+            Money::from_minor(100),
+        ));
+        json.add_recurring(RecurringBooking::new(
+            "Rent",
+            BookingType::Expense,
+            "f",
+            Money::from_minor(80000),
+            Recurrence {
+                count: Some(4),
+                ..Recurrence::new(RecurringType::Monthly, 3)
+            },
+        ))
+        .unwrap();
+
+        storage.import(&json).unwrap();
+        assert_eq!(storage.get_expenses().unwrap()[0].name, "Coffee");
+        assert_eq!(
+            storage.get_expenses().unwrap()[0].amount,
+            Money::from_minor(350)
+        );
+        assert_eq!(storage.get_expenses_archive().unwrap()[0].name, "Old");
+        assert_eq!(
+            storage.get_recurring().unwrap()[0].recurrence,
+            json.get_recurring().unwrap()[0].recurrence
+        );
+        assert_eq!(storage.get_categories().unwrap()[0].token, "f");
+
+        // a second import must not duplicate anything
+        assert!(storage.import(&json).is_err());
+
+        // ids deleted before the import stay unused
+        let last_ids = [("categories".to_string(), 5)].into_iter().collect();
+        storage.continue_ids(&last_ids).unwrap();
+        assert_eq!(storage.add_category(Category::new("Car", "c")).unwrap(), 6);
+    }
+
+    #[test]
+    fn test_update_and_delete() {
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        let mut coffee =
+            BudgetBooking::new("Coffee", BookingType::Expense, "f", Money::from_minor(350));
+        coffee.recurring_id = Some(4);
+        assert_eq!(storage.add_expense(coffee.clone()).unwrap(), 1);
+        assert_eq!(storage.get_expenses().unwrap()[0].recurring_id, Some(4));
+        assert_eq!(storage.add_expense(coffee).unwrap(), 2);
+        storage
+            .archive_expenses_before(Utc::now() + chrono::Duration::days(1))
+            .unwrap();
+
+        let mut archived = storage.get_expenses_archive().unwrap().remove(0);
+        archived.amount = Money::from_minor(400);
+        storage.update_expense(archived).unwrap();
+        assert_eq!(
+            storage.get_expenses_archive().unwrap()[0].amount,
+            Money::from_minor(400)
+        );
+        assert_eq!(storage.delete_expense(2).unwrap().id, 2);
+        assert!(storage.delete_expense(2).is_err());
+        // an explicit id must not replace another booking
+        let mut clash = storage.get_expenses_archive().unwrap().remove(0);
+        clash.amount = Money::from_minor(1);
+        assert!(storage.add_expense(clash).is_err());
+        assert_eq!(
+            storage.get_expenses_archive().unwrap()[0].amount,
+            Money::from_minor(400)
+        );
+
+        let id = storage.add_category(Category::new("Food", "f")).unwrap();
+        let mut cat = Category::new("Groceries", "g");
+        cat.id = id;
+        cat.parent = Some("h".to_string());
+        storage.update_category(cat).unwrap();
+        assert_eq!(storage.get_categories().unwrap()[0].token, "g");
+        assert_eq!(
+            storage.get_categories().unwrap()[0].parent.as_deref(),
+            Some("h")
+        );
+        cat = Category::new("Ghost", "x");
+        cat.id = 99;
+        assert!(storage.update_category(cat).is_err());
+        assert_eq!(storage.get_categories().unwrap().len(), 1);
+
+        let period = NaiveDate::from_ymd_opt(2026, 10, 1).unwrap();
+        let mut assignment = Assignment {
+            period,
+            category_token: "g".to_string(),
+            amount: Money::from_minor(5000),
+        };
+        storage.set_assignment(assignment.clone()).unwrap();
+        assignment.amount = Money::from_minor(7500);
+        storage.set_assignment(assignment.clone()).unwrap();
+        assert_eq!(storage.get_assignments().unwrap(), vec![assignment]);
+        storage.delete_assignment(period, "g").unwrap();
+        assert!(storage.get_assignments().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_set_setting() {
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        for day in ["25", "1"] {
+            storage
+                .set_setting(Setting {
+                    key: "Period_Start_Day".to_string(),
+                    value: day.to_string(),
+                })
+                .unwrap();
+        }
+        assert_eq!(storage.get_settings().unwrap().len(), 1);
+        assert_eq!(storage.get_setting_period_start_day().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_float_amounts_are_converted_to_cents() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+        conn.execute(
+            "INSERT INTO bookings (name, kind, category_token, amount, date)
+             VALUES ('E2', 'Expense', 'tt', 33.12, 0)",
+            [],
+        )
+        .unwrap();
+
+        let mut storage = SqliteStorage { conn };
+        storage.migrate(false).unwrap();
+        assert_eq!(
+            storage.get_expenses().unwrap()[0].amount,
+            Money::from_minor(3312)
+        );
+    }
+
+    #[test]
+    fn test_read_only_needs_current_schema() {
+        let dir = TempDir::new("schema");
+        let conn = Connection::open(dir.join(DB_FILE_SQLITE)).unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+        drop(conn);
+
+        let error = SqliteStorage::open(&dir, true).err().unwrap().to_string();
+        assert!(error.contains("without --read-only"), "{}", error);
+        SqliteStorage::open(&dir, false).unwrap();
+        SqliteStorage::open(&dir, true).unwrap();
+    }
+
+    #[test]
+    fn test_category_tokens_are_unique() {
+        let dir = TempDir::new("sqlite-unique-tokens");
+        let conn = Connection::open(dir.join(DB_FILE_SQLITE)).unwrap();
+        for migration in &MIGRATIONS[..11] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 11).unwrap();
+        conn.execute_batch(
+            "INSERT INTO categories (token, name) VALUES ('f', 'Food'), ('f', 'Fuel');",
+        )
+        .unwrap();
+        drop(conn);
+
+        // the duplicates aren't renamed behind the user's back, check has to repair them
+        let error = SqliteStorage::open(&dir, false).err().unwrap().to_string();
+        assert!(error.contains("bum check --repair"), "{}", error);
+        let storage = SqliteStorage::open_for_check(&dir).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), 11);
+        let problems = check::find_problems(&storage).unwrap();
+        check::repair(&problems, &storage).unwrap();
+        drop(storage);
+
+        let storage = SqliteStorage::open(&dir, false).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), MIGRATIONS.len());
+        let names: Vec<String> = storage
+            .get_categories()
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(names, vec!["Food"]);
+        storage.add_category(Category::new("Fuel", "u")).unwrap();
+
+        assert!(storage.add_category(Category::new("Fun", "f")).is_err());
+        let mut fuel = storage.get_categories().unwrap().remove(1);
+        fuel.token = "f".to_string();
+        assert!(storage.update_category(fuel).is_err());
+        assert_eq!(storage.get_categories().unwrap().len(), 2);
+
+        let json = MemoryStorage::default();
+        json.categories
+            .borrow_mut()
+            .extend([Category::new("Food", "f"), Category::new("Fuel", "f")]);
+        let mut target = SqliteStorage::open_in_memory().unwrap();
+        let error = target.import(&json).unwrap_err().to_string();
+        assert!(error.contains("bum check --repair"), "{}", error);
+    }
+
+    #[test]
+    fn test_invalid_date_names_its_column() {
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        storage
+            .conn
+            .execute(
+                "INSERT INTO bookings (name, kind, category_token, amount, date)
+                 VALUES ('E', 'Expense', 'f', 100, ?1)",
+                [i64::MAX],
+            )
+            .unwrap();
+        // date is the 7th column of the query
+        let error = storage.get_expenses().unwrap_err().to_string();
+        assert!(error.contains("at index 6"), "{}", error);
+    }
+}
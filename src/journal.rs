@@ -0,0 +1,387 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    archive,
+    db::Storage,
+    model::{Assignment, BudgetBooking, Category, ExchangeRate, RecurringBooking},
+};
+
+/// Only the most recent changes can be undone.
+const MAX_HISTORY: usize = 100;
+
+/// A reversible mutation of the ledger, holding everything needed to apply it in both directions.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Change {
+    AddBooking(BudgetBooking),
+    UpdateBooking {
+        before: BudgetBooking,
+        after: BudgetBooking,
+    },
+    DeleteBooking(BudgetBooking),
+    AddRecurring(RecurringBooking),
+    UpdateRecurring {
+        before: RecurringBooking,
+        after: RecurringBooking,
+    },
+    DeleteRecurring(RecurringBooking),
+    AddCategory(Category),
+    UpdateCategory {
+        before: Category,
+        after: Category,
+    },
+    DeleteCategory(Category),
+    /// `None` means no rate was set for the currency.
+    SetExchangeRate {
+        currency: String,
+        before: Option<ExchangeRate>,
+        after: Option<ExchangeRate>,
+    },
+    SetAssignment {
+        period: NaiveDate,
+        category_token: String,
+        before: Option<Assignment>,
+        after: Option<Assignment>,
+    },
+    /// Changes applied in order and undone together, e.g. reassigning bookings and deleting their
+    /// category.
+    Batch(Vec<Change>),
+}
+
+impl Change {
+    /// Groups `changes`, a single change stays as it is.
+    pub fn batch(mut changes: Vec<Change>) -> Change {
+        if changes.len() == 1 {
+            changes.remove(0)
+        } else {
+            Change::Batch(changes)
+        }
+    }
+
+    pub fn inverse(self) -> Change {
+        match self {
+            Change::AddBooking(b) => Change::DeleteBooking(b),
+            Change::UpdateBooking { before, after } => Change::UpdateBooking {
+                before: after,
+                after: before,
+            },
+            Change::DeleteBooking(b) => Change::AddBooking(b),
+            Change::AddRecurring(r) => Change::DeleteRecurring(r),
+            Change::UpdateRecurring { before, after } => Change::UpdateRecurring {
+                before: after,
+                after: before,
+            },
+            Change::DeleteRecurring(r) => Change::AddRecurring(r),
+            Change::AddCategory(c) => Change::DeleteCategory(c),
+            Change::UpdateCategory { before, after } => Change::UpdateCategory {
+                before: after,
+                after: before,
+            },
+            Change::DeleteCategory(c) => Change::AddCategory(c),
+            Change::SetExchangeRate {
+                currency,
+                before,
+                after,
+            } => Change::SetExchangeRate {
+                currency,
+                before: after,
+                after: before,
+            },
+            Change::SetAssignment {
+                period,
+                category_token,
+                before,
+                after,
+            } => Change::SetAssignment {
+                period,
+                category_token,
+                before: after,
+                after: before,
+            },
+            Change::Batch(changes) => {
+                Change::Batch(changes.into_iter().rev().map(Change::inverse).collect())
+            }
+        }
+    }
+
+    /// The days of the bookings this change touches.
+    fn booking_dates(&self) -> Vec<NaiveDate> {
+        match self {
+            Change::AddBooking(b) | Change::DeleteBooking(b) => vec![b.local_date()],
+            Change::UpdateBooking { before, after } => {
+                vec![before.local_date(), after.local_date()]
+            }
+            Change::Batch(changes) => changes.iter().flat_map(Change::booking_dates).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Writes the change to `storage` and returns it as applied, e.g. with the assigned id of an
+    /// added record, so that its inverse undoes exactly this. Next to it comes the result of
+    /// updating the summaries, whose failure doesn't undo the write, so it still gets recorded.
+    fn apply(self, storage: &dyn Storage) -> Result<(Change, Result<()>)> {
+        let applied = self.write(storage)?;
+        // Bookings of closed periods belong into the archive and count towards their summaries
+        let summaries = archive::resummarize(storage, &applied.booking_dates());
+        Ok((applied, summaries))
+    }
+
+    fn write(self, storage: &dyn Storage) -> Result<Change> {
+        Ok(match self {
+            Change::AddBooking(mut b) => {
+                b.id = storage.add_expense(b.clone())?;
+                Change::AddBooking(b)
+            }
+            Change::UpdateBooking { before, after } => {
+                storage.update_expense(after.clone())?;
+                Change::UpdateBooking { before, after }
+            }
+            Change::DeleteBooking(b) => Change::DeleteBooking(storage.delete_expense(b.id)?),
+            Change::AddRecurring(mut r) => {
+                r.id = storage.add_recurring(r.clone())?;
+                Change::AddRecurring(r)
+            }
+            Change::UpdateRecurring { before, after } => {
+                storage.update_recurring(after.clone())?;
+                Change::UpdateRecurring { before, after }
+            }
+            Change::DeleteRecurring(r) => Change::DeleteRecurring(storage.delete_recurring(r.id)?),
+            Change::AddCategory(mut c) => {
+                c.id = storage.add_category(c.clone())?;
+                Change::AddCategory(c)
+            }
+            Change::UpdateCategory { before, after } => {
+                storage.update_category(after.clone())?;
+                Change::UpdateCategory { before, after }
+            }
+            Change::DeleteCategory(c) => Change::DeleteCategory(storage.delete_category(c.id)?),
+            Change::SetExchangeRate {
+                currency,
+                before,
+                after,
+            } => {
+                match &after {
+                    Some(rate) => storage.set_exchange_rate(rate.clone())?,
+                    None => storage.delete_exchange_rate(&currency)?,
+                }
+                Change::SetExchangeRate {
+                    currency,
+                    before,
+                    after,
+                }
+            }
+            Change::SetAssignment {
+                period,
+                category_token,
+                before,
+                after,
+            } => {
+                match &after {
+                    Some(assignment) => storage.set_assignment(assignment.clone())?,
+                    None => storage.delete_assignment(period, &category_token)?,
+                }
+                Change::SetAssignment {
+                    period,
+                    category_token,
+                    before,
+                    after,
+                }
+            }
+            Change::Batch(changes) => {
+                let mut applied = vec![];
+                for change in changes {
+                    match change.write(storage) {
+                        Ok(change) => applied.push(change),
+                        Err(e) => {
+                            // A batch is all or nothing, take back what it already wrote
+                            for change in applied.into_iter().rev() {
+                                change.inverse().write(storage).with_context(|| {
+                                    format!("{}, undoing the rest of the change failed", e)
+                                })?;
+                            }
+                            return Err(e);
+                        }
+                    }
+                }
+                Change::Batch(applied)
+            }
+        })
+    }
+}
+
+/// Undo and redo stacks, most recent change last.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Journal {
+    pub undo: Vec<Change>,
+    pub redo: Vec<Change>,
+}
+
+/// Applies `change` and records it, which discards everything that could have been redone.
+pub fn apply(change: Change, storage: &dyn Storage) -> Result<()> {
+    let mut summaries = Ok(());
+    storage.with_lock(&mut || {
+        let (applied, result) = change.clone().apply(storage)?;
+        summaries = result;
+        let mut journal = storage.get_journal()?;
+        journal.undo.push(applied);
+        if journal.undo.len() > MAX_HISTORY {
+            journal.undo.remove(0);
+        }
+        journal.redo.clear();
+        storage.save_journal(&journal)
+    })?;
+    summaries
+}
+
+pub fn undo(storage: &dyn Storage) -> Result<()> {
+    let mut summaries = Ok(());
+    storage.with_lock(&mut || {
+        let mut journal = storage.get_journal()?;
+        let change = journal
+            .undo
+            .pop()
+            .ok_or_else(|| anyhow!("Nothing to undo"))?;
+        let (undone, result) = change.inverse().apply(storage)?;
+        summaries = result;
+        journal.redo.push(undone.inverse());
+        storage.save_journal(&journal)
+    })?;
+    summaries
+}
+
+pub fn redo(storage: &dyn Storage) -> Result<()> {
+    let mut summaries = Ok(());
+    storage.with_lock(&mut || {
+        let mut journal = storage.get_journal()?;
+        let change = journal
+            .redo
+            .pop()
+            .ok_or_else(|| anyhow!("Nothing to redo"))?;
+        let (redone, result) = change.apply(storage)?;
+        summaries = result;
+        journal.undo.push(redone);
+        storage.save_journal(&journal)
+    })?;
+    summaries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::handle_command,
+        db::MemoryStorage,
+        model::{BookingType, Setting},
+    };
+
+    #[test]
+    fn test_undo_and_redo_commands() {
+        let storage = MemoryStorage::default();
+        storage.add_category(Category::new("Food", "f")).unwrap();
+        handle_command("ae Coffee f 3.50", &storage).unwrap();
+        handle_command("ee 1 amount=4.00", &storage).unwrap();
+        handle_command("ax USD 0.9", &storage).unwrap();
+
+        undo(&storage).unwrap();
+        assert!(storage.get_exchange_rates().unwrap().is_empty());
+        undo(&storage).unwrap();
+        assert_eq!(
+            storage.get_expenses().unwrap()[0].amount.to_string(),
+            "3.50"
+        );
+        undo(&storage).unwrap();
+        assert!(storage.get_expenses().unwrap().is_empty());
+        assert!(undo(&storage).is_err());
+
+        redo(&storage).unwrap();
+        redo(&storage).unwrap();
+        let bookings = storage.get_expenses().unwrap();
+        assert_eq!(
+            (bookings[0].id, bookings[0].amount.to_string()),
+            (1, "4.00".to_string())
+        );
+
+        // a new change drops what could have been redone
+        handle_command("de 1", &storage).unwrap();
+        assert!(redo(&storage).is_err());
+        undo(&storage).unwrap();
+        assert_eq!(storage.get_expenses().unwrap()[0].id, 1);
+    }
+
+    #[test]
+    fn test_failed_batch_is_rolled_back() {
+        let storage = MemoryStorage::default();
+        storage.add_category(Category::new("Food", "f")).unwrap();
+        handle_command("ae Coffee f 3.50", &storage).unwrap();
+        let before = storage.get_expenses().unwrap().remove(0);
+        let mut after = before.clone();
+        after.category_token = "g".to_string();
+        let mut missing = Category::new("Ghost", "x");
+        missing.id = 99;
+
+        let batch = Change::batch(vec![
+            Change::UpdateBooking {
+                before: before.clone(),
+                after,
+            },
+            Change::DeleteCategory(missing),
+        ]);
+        assert!(apply(batch, &storage).is_err());
+        assert_eq!(storage.get_expenses().unwrap()[0], before);
+        assert_eq!(storage.get_categories().unwrap().len(), 1);
+        assert_eq!(storage.get_journal().unwrap().undo.len(), 1);
+    }
+
+    #[test]
+    fn test_change_is_recorded_when_summaries_fail() {
+        let storage = MemoryStorage::default();
+        storage.add_category(Category::new("Food", "f")).unwrap();
+        // breaks loading the periods to summarize, but not writing the booking
+        storage
+            .set_setting(Setting {
+                key: "Period_Start_Day".to_string(),
+                value: "99".to_string(),
+            })
+            .unwrap();
+
+        let booking =
+            BudgetBooking::new("Coffee", BookingType::Expense, "f", "3.50".parse().unwrap());
+        assert!(apply(Change::AddBooking(booking), &storage).is_err());
+        assert_eq!(storage.get_expenses().unwrap().len(), 1);
+        assert_eq!(storage.get_journal().unwrap().undo.len(), 1);
+
+        assert!(undo(&storage).is_err());
+        assert!(storage.get_expenses().unwrap().is_empty());
+        assert_eq!(storage.get_journal().unwrap().redo.len(), 1);
+    }
+
+    #[test]
+    fn test_undo_delete_after_new_booking() {
+        let storage = MemoryStorage::default();
+        storage.add_category(Category::new("Food", "f")).unwrap();
+        handle_command("ae Coffee f 3.50", &storage).unwrap();
+        handle_command("ae Lunch f 9.00", &storage).unwrap();
+        handle_command("de 2", &storage).unwrap();
+        // e.g. the scheduler booking something while the delete can still be undone
+        let id = storage
+            .add_expense(BudgetBooking::new(
+                "Rent",
+                BookingType::Expense,
+                "f",
+                "800".parse().unwrap(),
+            ))
+            .unwrap();
+        assert_eq!(id, 3);
+
+        undo(&storage).unwrap();
+        let mut ids: Vec<u64> = storage
+            .get_expenses()
+            .unwrap()
+            .iter()
+            .map(|b| b.id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+}
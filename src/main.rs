@@ -1,10 +1,123 @@
+use std::path::{Path, PathBuf};
+
+use clap::{ArgEnum, Parser, Subcommand};
+
 // use model::{BookEntry, EntryType};
+mod archive;
+mod check;
 mod commands;
 mod dataservice;
 mod db;
+mod journal;
 mod model;
+mod money;
+mod period;
+mod scheduler;
+#[cfg(test)]
+mod testutil;
 mod ui;
+
+/// personal budget and finance manager
+#[derive(Parser)]
+#[clap(version)]
+struct Cli {
+    /// Directory holding the data files. Falls back to $BUM_DATA_DIR, then $XDG_DATA_HOME/bum.
+    #[clap(long, value_name = "DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Open the data files without modifying them, e.g. while another instance is writing.
+    #[clap(long)]
+    read_only: bool,
+
+    #[clap(subcommand)]
+    command: Option<CliCommand>,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Import the existing data files into another storage backend
+    Migrate {
+        #[clap(long, arg_enum)]
+        to: Backend,
+    },
+    /// Report bookings referring to unknown categories and categories sharing a token
+    Check {
+        /// Add the missing categories and remove duplicates, can be undone in the UI
+        #[clap(long)]
+        repair: bool,
+    },
+}
+
+#[derive(Clone, ArgEnum)]
+enum Backend {
+    Sqlite,
+}
+
 fn main() {
-    db::ensure_db_files_exist().unwrap();
-    ui::draw().expect("Error starting UI");
+    let cli = Cli::parse();
+    let data_dir = db::resolve_data_dir(cli.data_dir).expect("Unable to resolve data directory");
+
+    match cli.command {
+        Some(CliCommand::Migrate {
+            to: Backend::Sqlite,
+        }) => match db::migrate_to_sqlite(&data_dir) {
+            Ok(path) => println!(
+                "Imported data into {}, the JSON files are no longer used.",
+                path.display()
+            ),
+            Err(e) => {
+                eprintln!("Migration failed: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Some(CliCommand::Check { repair }) => {
+            let storage = open_storage(db::open_for_check(&data_dir, cli.read_only), &data_dir);
+            let problems = check::find_problems(storage.as_ref()).expect("Unable to check data");
+            if problems.is_empty() {
+                println!("No problems found.");
+                return;
+            }
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            if repair {
+                match check::repair(&problems, storage.as_ref()) {
+                    Ok(()) => println!("Repaired {} problem(s).", problems.len()),
+                    Err(e) => {
+                        eprintln!("Repair failed: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            } else {
+                println!("Run bum check --repair to fix them.");
+                std::process::exit(1);
+            }
+        }
+        None => {
+            let storage = open_storage(db::open(&data_dir, cli.read_only), &data_dir);
+            if !cli.read_only {
+                let today = chrono::Local::now().date_naive();
+                scheduler::run(storage.as_ref(), today).expect("Unable to book recurring items");
+                archive::rollover(storage.as_ref()).expect("Unable to archive closed periods");
+            }
+            let history = (!cli.read_only).then(|| data_dir.join("data_command_history.txt"));
+            if let Err(e) = ui::draw(storage, history) {
+                eprintln!("Error starting UI: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn open_storage(
+    opened: anyhow::Result<Box<dyn db::Storage>>,
+    data_dir: &Path,
+) -> Box<dyn db::Storage> {
+    match opened {
+        Ok(storage) => storage,
+        Err(e) => {
+            eprintln!("Unable to open data files in {}: {}", data_dir.display(), e);
+            std::process::exit(1);
+        }
+    }
 }
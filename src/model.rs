@@ -2,63 +2,253 @@ use std::fmt::Display;
 
 use chrono::serde::ts_seconds;
 use chrono::DateTime;
+use chrono::Datelike;
+use chrono::Days;
+use chrono::Local;
+use chrono::Months;
+use chrono::NaiveDate;
+use chrono::TimeZone;
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 
-#[derive(Default, Serialize, Debug, Deserialize, PartialEq)]
+use crate::money::Money;
+
+#[derive(Default, Serialize, Debug, Deserialize, PartialEq, Clone)]
 pub enum BookingType {
     #[default]
     Income,
     Expense,
 }
 
-#[derive(Serialize, Debug, Deserialize)]
+/// Entities with a stable id, assigned by the storage when they are added.
+pub trait Record {
+    fn id(&self) -> u64;
+    fn set_id(&mut self, id: u64);
+}
+
+impl Record for BudgetBooking {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn set_id(&mut self, id: u64) {
+        self.id = id;
+    }
+}
+
+impl Record for RecurringBooking {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn set_id(&mut self, id: u64) {
+        self.id = id;
+    }
+}
+
+impl Record for Category {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn set_id(&mut self, id: u64) {
+        self.id = id;
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
 pub struct BudgetBooking {
+    /// 0 until the booking has been stored.
+    #[serde(default)]
+    pub id: u64,
     pub name: String,
     pub kind: BookingType,
     pub category_token: String,
-    pub amount: f32,
+    pub amount: Money,
+    /// ISO 4217 code, empty means the base currency.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub currency: String,
     #[serde(with = "ts_seconds")]
     pub date: DateTime<Utc>,
+    /// Booked by the scheduler from a recurring item, so already part of the recurring totals.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub recurring: bool,
+    /// The recurring item it was booked from, missing for bookings of older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurring_id: Option<u64>,
 }
 
 impl BudgetBooking {
-    pub fn new(name: &str, kind: BookingType, category_token: &str, amount: f32) -> Self {
+    pub fn new(name: &str, kind: BookingType, category_token: &str, amount: Money) -> Self {
         Self {
+            id: 0,
             name: name.to_string(),
             kind,
             category_token: category_token.to_string(),
             amount,
+            currency: String::new(),
             date: chrono::offset::Utc::now(),
+            recurring: false,
+            recurring_id: None,
         }
     }
+
+    pub fn in_currency(mut self, currency: &str) -> Self {
+        self.currency = currency.to_string();
+        self
+    }
+
+    /// Dates the booking at local noon of `date`, clear of the day boundaries.
+    pub fn on_date(mut self, date: NaiveDate) -> Self {
+        let noon = date.and_hms_opt(12, 0, 0).unwrap_or_default();
+        self.date = Local
+            .from_local_datetime(&noon)
+            .earliest()
+            .map(|d| d.with_timezone(&Utc))
+            .unwrap_or_else(|| Utc.from_utc_datetime(&noon));
+        self
+    }
+
+    /// The calendar day of the booking in local time, which decides its budget period.
+    pub fn local_date(&self) -> NaiveDate {
+        self.date.with_timezone(&Local).naive_local().date()
+    }
 }
 
-#[derive(Default, Serialize, Debug, Deserialize, PartialEq)]
+#[derive(Default, Serialize, Debug, Deserialize, PartialEq, Clone)]
 pub enum RecurringType {
+    Weekly,
     #[default]
     Monthly,
     Yearly,
 }
 
-impl Display for RecurringType {
+/// When a recurring item is due: every `interval` weeks, months or years from its anchor date,
+/// optionally ending after a date or a number of occurrences.
+#[derive(Serialize, Debug, Deserialize, PartialEq, Clone)]
+pub struct Recurrence {
+    pub rate_type: RecurringType,
+    #[serde(default = "Recurrence::default_interval")]
+    pub interval: u32,
+    /// Last day an occurrence may fall on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<NaiveDate>,
+    /// Total number of occurrences, counted from the anchor date.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+}
+
+impl Recurrence {
+    pub fn new(rate_type: RecurringType, interval: u32) -> Self {
+        Recurrence {
+            rate_type,
+            interval: interval.max(1),
+            until: None,
+            count: None,
+        }
+    }
+
+    pub fn monthly() -> Self {
+        Recurrence::new(RecurringType::Monthly, 1)
+    }
+
+    fn default_interval() -> u32 {
+        1
+    }
+
+    /// The `n`th occurrence counted from `anchor`, clamped to the end of shorter months.
+    ///
+    /// `None` once the recurrence has ended.
+    pub fn nth_occurrence(&self, anchor: NaiveDate, n: u32) -> Option<NaiveDate> {
+        if self.count.is_some_and(|count| n >= count) {
+            return None;
+        }
+        let steps = self.interval.max(1).checked_mul(n)?;
+        let date = match self.rate_type {
+            RecurringType::Weekly => anchor.checked_add_days(Days::new(7 * steps as u64)),
+            RecurringType::Monthly => anchor.checked_add_months(Months::new(steps)),
+            RecurringType::Yearly => anchor.checked_add_months(Months::new(steps.checked_mul(12)?)),
+        }?;
+        match self.until {
+            Some(until) if date > until => None,
+            _ => Some(date),
+        }
+    }
+
+    /// At most as many occurrences counted from `anchor` as lie before `date`, so searching from
+    /// there doesn't have to start over at the anchor.
+    fn occurrences_before(&self, anchor: NaiveDate, date: NaiveDate) -> u32 {
+        let months =
+            (date.year() - anchor.year()) as i64 * 12 + date.month() as i64 - anchor.month() as i64;
+        let steps = match self.rate_type {
+            RecurringType::Weekly => (date - anchor).num_days() / 7,
+            RecurringType::Monthly => months,
+            RecurringType::Yearly => months / 12,
+        } / self.interval.max(1) as i64;
+        // One less, as the day of the month may not have been reached yet
+        (steps - 1).clamp(0, u32::MAX as i64) as u32
+    }
+
+    /// The average amount per month, e.g. a quarterly amount divided by 3.
+    ///
+    /// Weekly amounts use the average Gregorian year of 365.2425 days, so 4 weekly payments
+    /// of 100 are not mistaken for a monthly 400.
+    pub fn monthly_amount(&self, amount: Money) -> Money {
+        let interval = self.interval.max(1) as i64;
+        match self.rate_type {
+            // 365.2425 / 7 / 12 = 146097 / 33600 weeks per month
+            RecurringType::Weekly => amount.mul_ratio(146097, 33600 * interval),
+            RecurringType::Monthly => amount.mul_ratio(1, interval),
+            RecurringType::Yearly => amount.mul_ratio(1, 12 * interval),
+        }
+    }
+
+    /// Whether the recurrence is billed exactly once a month, so its amount is already monthly.
+    pub fn is_monthly(&self) -> bool {
+        self.rate_type == RecurringType::Monthly && self.interval <= 1
+    }
+}
+
+impl Default for Recurrence {
+    fn default() -> Self {
+        Recurrence::monthly()
+    }
+}
+
+/// Short form for tables, e.g. `M`, `3M` or `2W`.
+impl Display for Recurrence {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
+        if self.interval > 1 {
+            write!(f, "{}", self.interval)?;
+        }
+        match self.rate_type {
+            RecurringType::Weekly => write!(f, "W"),
             RecurringType::Monthly => write!(f, "M"),
             RecurringType::Yearly => write!(f, "Y"),
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct RecurringBooking {
+    /// 0 until the item has been stored.
+    #[serde(default)]
+    pub id: u64,
     pub name: String,
     pub kind: BookingType,
     pub category_token: String,
-    pub amount: f32,
-    pub rate_type: RecurringType,
-    // #[serde(serialize_with = "to_ts")]
-    // pub next_payment_date: DateTime<Utc>,
+    pub amount: Money,
+    /// ISO 4217 code, empty means the base currency.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub currency: String,
+    #[serde(flatten)]
+    pub recurrence: Recurrence,
+    /// First occurrence, all later ones are derived from it.
+    #[serde(default)]
+    pub anchor_date: Option<NaiveDate>,
+    /// The next occurrence that has not been booked yet.
+    #[serde(default)]
+    pub next_due: Option<NaiveDate>,
 }
 
 impl RecurringBooking {
@@ -66,35 +256,132 @@ impl RecurringBooking {
         name: &str,
         kind: BookingType,
         category_token: &str,
-        amount: f32,
-        rate_type: RecurringType,
+        amount: Money,
+        recurrence: Recurrence,
     ) -> Self {
+        let today = Local::now().naive_local().date();
         Self {
+            id: 0,
             name: name.to_string(),
             kind,
             category_token: category_token.to_string(),
             amount,
-            rate_type,
+            currency: String::new(),
+            recurrence,
+            anchor_date: Some(today),
+            next_due: Some(today),
         }
     }
+
+    pub fn in_currency(mut self, currency: &str) -> Self {
+        self.currency = currency.to_string();
+        self
+    }
+
+    /// The first occurrence strictly after `date`.
+    pub fn occurrence_after(&self, date: NaiveDate) -> Option<NaiveDate> {
+        let anchor = self.anchor_date?;
+        (self.recurrence.occurrences_before(anchor, date)..)
+            .map(|n| self.recurrence.nth_occurrence(anchor, n))
+            .find(|d| d.is_none_or(|d| d > date))
+            .flatten()
+    }
+
+    /// Whether the last occurrence lies before `date`, so the item no longer counts from then on.
+    pub fn ended_before(&self, date: NaiveDate) -> bool {
+        self.anchor_date.is_some()
+            && date
+                .pred_opt()
+                .and_then(|d| self.occurrence_after(d))
+                .is_none()
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Category {
+    /// 0 until the category has been stored.
+    #[serde(default)]
+    pub id: u64,
     pub token: String,
     pub name: String,
+    /// Token of the category this one is a subcategory of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Monthly spending limit, including the subcategories.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<Money>,
 }
 
 impl Category {
     pub fn new(name: &str, token: &str) -> Self {
         Category {
+            id: 0,
             name: name.to_string(),
             token: token.to_string(),
+            parent: None,
+            limit: None,
+        }
+    }
+}
+
+/// Tokens from the top-level category down to `token`. Stops at a loop of parents, which only
+/// hand edited data files can contain.
+pub fn category_path<'a>(categories: &'a [Category], token: &'a str) -> Vec<&'a str> {
+    let mut path = vec![token];
+    let mut current = token;
+    while let Some(parent) = categories
+        .iter()
+        .find(|c| c.token == current)
+        .and_then(|c| c.parent.as_deref())
+    {
+        if path.contains(&parent) {
+            break;
+        }
+        path.push(parent);
+        current = parent;
+    }
+    path.reverse();
+    path
+}
+
+/// Totals of a closed budget period, written when its bookings are archived.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PeriodSummary {
+    pub start: NaiveDate,
+    /// Last day of the period.
+    pub end: NaiveDate,
+    pub income: Money,
+    pub recurring_expenses: Money,
+    pub budget_spent: Money,
+    pub budget_left: Money,
+}
+
+/// Money put into the envelope of a category for one budget period, in envelope mode.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Assignment {
+    /// First day of the budget period.
+    pub period: NaiveDate,
+    pub category_token: String,
+    pub amount: Money,
+}
+
+/// How many units of the base currency one unit of `currency` is worth.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExchangeRate {
+    pub currency: String,
+    pub rate: f64,
+}
+
+impl ExchangeRate {
+    pub fn new(currency: &str, rate: f64) -> Self {
+        ExchangeRate {
+            currency: currency.to_string(),
+            rate,
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Setting {
     pub key: String,
     pub value: String,
@@ -0,0 +1,284 @@
+use std::{
+    fmt::Display,
+    iter::Sum,
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+    str::FromStr,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+#[cfg(feature = "sqlite")]
+use rusqlite::types::{FromSql, FromSqlResult, ToSqlOutput, ValueRef};
+
+/// An exact amount of money, stored as integer minor units (cents).
+///
+/// Serialized as a decimal string like `"12.50"`. Plain JSON numbers written by older versions
+/// are still accepted and rounded to the nearest cent once when read.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+#[derive(Error, Debug, PartialEq)]
+#[error("'{0}' is not a valid amount")]
+pub struct ParseMoneyError(String);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+    const MINOR_PER_MAJOR: i64 = 100;
+    /// Largest amount accepted from input, ±10^13. Sums of many such amounts still fit into an
+    /// i64, so the arithmetic below can't overflow on anything the parser let through.
+    const MAX_MINOR: i64 = 10_000_000_000_000 * Self::MINOR_PER_MAJOR;
+
+    pub fn from_minor(minor: i64) -> Self {
+        Money(minor)
+    }
+
+    /// Rounds a legacy float amount to the nearest cent.
+    pub fn from_f64(amount: f64) -> Self {
+        Money::from_minor((amount * Self::MINOR_PER_MAJOR as f64).round() as i64)
+    }
+
+    /// `minor` as an amount, unless it is above the largest accepted one.
+    fn checked(minor: i64) -> Option<Self> {
+        (minor.checked_abs()? <= Self::MAX_MINOR).then_some(Money(minor))
+    }
+
+    /// Converts into another currency, rounding to the nearest cent once.
+    pub fn convert(self, rate: f64) -> Self {
+        Money((self.0 as f64 * rate).round() as i64)
+    }
+
+    /// Multiplies by `numerator / denominator`, rounding half away from zero.
+    pub fn mul_ratio(self, numerator: i64, denominator: i64) -> Self {
+        let value = self.0 as i128 * numerator as i128;
+        let denominator = denominator as i128;
+        let rounded = (value.abs() * 2 + denominator.abs()) / (denominator.abs() * 2);
+        let negative = (value < 0) != (denominator < 0);
+        Money(if negative { -rounded } else { rounded } as i64)
+    }
+
+    /// The share of `total` this amount is, e.g. 0.5 for half of it and 0 if `total` is zero.
+    pub fn ratio_of(self, total: Money) -> f64 {
+        if total.0 == 0 {
+            return 0.0;
+        }
+        self.0 as f64 / total.0 as f64
+    }
+}
+
+impl Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        let per_major = Self::MINOR_PER_MAJOR as u64;
+        write!(f, "{}{}.{:02}", sign, abs / per_major, abs % per_major)
+    }
+}
+
+impl FromStr for Money {
+    type Err = ParseMoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseMoneyError(s.to_string());
+        let trimmed = s.trim();
+        let (negative, digits) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        let (major, minor) = digits.split_once('.').unwrap_or((digits, ""));
+
+        let all_digits = |p: &str| p.chars().all(|c| c.is_ascii_digit());
+        if (major.is_empty() && minor.is_empty())
+            || minor.len() > 2
+            || !all_digits(major)
+            || !all_digits(minor)
+        {
+            return Err(err());
+        }
+
+        let major: i64 = if major.is_empty() {
+            0
+        } else {
+            major.parse().map_err(|_| err())?
+        };
+        let minor: i64 = format!("{:0<2}", minor).parse().map_err(|_| err())?;
+        let value = major
+            .checked_mul(Self::MINOR_PER_MAJOR)
+            .and_then(|m| m.checked_add(minor))
+            .and_then(Money::checked)
+            .ok_or_else(err)?;
+        Ok(if negative { -value } else { value })
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Text(String),
+            Integer(i64),
+            Float(f64),
+        }
+
+        let out_of_range =
+            |amount: &dyn Display| de::Error::custom(format!("amount {} is out of range", amount));
+        match Repr::deserialize(deserializer)? {
+            Repr::Text(s) => s.parse().map_err(de::Error::custom),
+            Repr::Integer(i) => i
+                .checked_mul(Self::MINOR_PER_MAJOR)
+                .and_then(Money::checked)
+                .ok_or_else(|| out_of_range(&i)),
+            Repr::Float(f) => Money::checked(Money::from_f64(f).0).ok_or_else(|| out_of_range(&f)),
+        }
+    }
+}
+
+/// Stored as integer cents.
+#[cfg(feature = "sqlite")]
+impl rusqlite::ToSql for Money {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.0.into())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl FromSql for Money {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        i64::column_result(value).map(Money)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, rhs: Money) -> Money {
+        Money(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, rhs: Money) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, rhs: Money) -> Money {
+        Money(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, rhs: Money) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+impl Mul<i64> for Money {
+    type Output = Money;
+
+    fn mul(self, rhs: i64) -> Money {
+        Money(self.0 * rhs)
+    }
+}
+
+/// Divides and rounds to the nearest cent, e.g. to spread a yearly amount over 12 months.
+impl Div<i64> for Money {
+    type Output = Money;
+
+    fn div(self, rhs: i64) -> Money {
+        self.mul_ratio(1, rhs)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Money>>(iter: I) -> Money {
+        iter.copied().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!("12.5".parse::<Money>().unwrap(), Money::from_minor(1250));
+        assert_eq!("-0.07".parse::<Money>().unwrap(), Money::from_minor(-7));
+        assert_eq!(".5".parse::<Money>().unwrap(), Money::from_minor(50));
+        assert_eq!("3".parse::<Money>().unwrap(), Money::from_minor(300));
+        assert!("1.234".parse::<Money>().is_err());
+        assert!("1,5".parse::<Money>().is_err());
+        assert!("abc".parse::<Money>().is_err());
+        assert!("".parse::<Money>().is_err());
+
+        assert_eq!(Money::from_minor(1250).to_string(), "12.50");
+        assert_eq!(Money::from_minor(-7).to_string(), "-0.07");
+    }
+
+    #[test]
+    fn test_largest_amount() {
+        let max: Money = "10000000000000".parse().unwrap();
+        assert_eq!("-10000000000000.00".parse::<Money>().unwrap(), -max);
+        assert!("10000000000000.01".parse::<Money>().is_err());
+        assert!("99999999999999999".parse::<Money>().is_err());
+        assert!(serde_json::from_str::<Money>("1e14").is_err());
+
+        // totals of many of them don't overflow
+        let total: Money = std::iter::repeat_n(max, 9000).sum();
+        assert_eq!(total, max * 9000);
+        assert_eq!(total / 12 * 12, total);
+        // a weekly item per month
+        assert!(max.mul_ratio(146097, 33600) > max * 4);
+    }
+
+    #[test]
+    fn test_division_rounds_half_away_from_zero() {
+        assert_eq!(Money::from_minor(47322) / 12, Money::from_minor(3944));
+        assert_eq!(Money::from_minor(-47322) / 12, Money::from_minor(-3944));
+        assert_eq!(Money::from_minor(100) / 3, Money::from_minor(33));
+    }
+
+    #[test]
+    fn test_serde_reads_legacy_floats() {
+        let amounts: Vec<Money> = serde_json::from_str(r#"[33.12, 3, "0.10", 0.1]"#).unwrap();
+        assert_eq!(
+            amounts,
+            vec![
+                Money::from_minor(3312),
+                Money::from_minor(300),
+                Money::from_minor(10),
+                Money::from_minor(10)
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&Money::from_minor(3312)).unwrap(),
+            r#""33.12""#
+        );
+        let error = serde_json::from_str::<Money>("92233720368547759").unwrap_err();
+        assert!(error.to_string().contains("out of range"), "{}", error);
+    }
+}
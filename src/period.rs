@@ -0,0 +1,143 @@
+use std::fmt::Display;
+
+use chrono::{Datelike, Local, NaiveDate};
+
+/// A monthly budget period starting on a configurable day, e.g. payday on the 25th.
+///
+/// Months shorter than the start day begin on their last day instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Period {
+    pub start: NaiveDate,
+    /// Exclusive, the first day of the following period.
+    pub end: NaiveDate,
+    start_day: u32,
+}
+
+impl Period {
+    pub fn containing(date: NaiveDate, start_day: u32) -> Self {
+        let start_day = start_day.clamp(1, 31);
+        let mut start = period_start(date.year(), date.month(), start_day);
+        if date < start {
+            let (year, month) = previous_month(date.year(), date.month());
+            start = period_start(year, month, start_day);
+        }
+        Self::starting_at(start, start_day)
+    }
+
+    pub fn current(start_day: u32) -> Self {
+        Self::containing(Local::now().naive_local().date(), start_day)
+    }
+
+    fn starting_at(start: NaiveDate, start_day: u32) -> Self {
+        let (year, month) = next_month(start.year(), start.month());
+        Period {
+            start,
+            end: period_start(year, month, start_day),
+            start_day,
+        }
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.start <= date && date < self.end
+    }
+
+    pub fn next(&self) -> Self {
+        Self::starting_at(self.end, self.start_day)
+    }
+
+    pub fn previous(&self) -> Self {
+        let (year, month) = previous_month(self.start.year(), self.start.month());
+        Self::starting_at(period_start(year, month, self.start_day), self.start_day)
+    }
+
+    /// The last day that still belongs to the period.
+    pub fn last_day(&self) -> NaiveDate {
+        self.end.pred_opt().unwrap_or(self.end)
+    }
+}
+
+impl Default for Period {
+    fn default() -> Self {
+        Period::current(1)
+    }
+}
+
+impl Display for Period {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.start_day == 1 {
+            write!(f, "{}", self.start.format("%B %Y"))
+        } else {
+            write!(f, "{} - {}", self.start, self.last_day())
+        }
+    }
+}
+
+fn period_start(year: i32, month: u32, start_day: u32) -> NaiveDate {
+    let day = start_day.min(days_in_month(year, month));
+    NaiveDate::from_ymd_opt(year, month, day).expect("day is clamped to the month")
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = next_month(year, month);
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .and_then(|d| d.pred_opt())
+        .map(|d| d.day())
+        .unwrap_or(28)
+}
+
+fn next_month(year: i32, month: u32) -> (i32, u32) {
+    if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    }
+}
+
+fn previous_month(year: i32, month: u32) -> (i32, u32) {
+    if month == 1 {
+        (year - 1, 12)
+    } else {
+        (year, month - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_calendar_month() {
+        let p = Period::containing(d(2026, 10, 14), 1);
+        assert_eq!(p.start, d(2026, 10, 1));
+        assert_eq!(p.end, d(2026, 11, 1));
+        assert_eq!(p.to_string(), "October 2026");
+        assert_eq!(p.next().start, d(2026, 11, 1));
+        assert_eq!(p.previous().start, d(2026, 9, 1));
+    }
+
+    #[test]
+    fn test_payday_start() {
+        let p = Period::containing(d(2026, 10, 14), 25);
+        assert_eq!(p.start, d(2026, 9, 25));
+        assert_eq!(p.last_day(), d(2026, 10, 24));
+        assert!(p.contains(d(2026, 10, 24)));
+        assert!(!p.contains(d(2026, 10, 25)));
+
+        let p = Period::containing(d(2026, 1, 3), 25);
+        assert_eq!(p.start, d(2025, 12, 25));
+        assert_eq!(p.next().start, d(2026, 1, 25));
+    }
+
+    #[test]
+    fn test_start_day_past_month_end() {
+        let p = Period::containing(d(2026, 3, 1), 31);
+        assert_eq!(p.start, d(2026, 2, 28));
+        assert_eq!(p.end, d(2026, 3, 31));
+        assert_eq!(p.next().end, d(2026, 4, 30));
+        assert_eq!(p.previous().start, d(2026, 1, 31));
+    }
+}
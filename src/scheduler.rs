@@ -0,0 +1,277 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+
+use crate::{
+    db::Storage,
+    model::{BudgetBooking, RecurringBooking},
+};
+
+/// Books every occurrence of the recurring items that fell due on or before `today`.
+///
+/// Occurrences are never booked twice: `next_due` is advanced past every booked date and a
+/// booking of the same item on the same day is skipped, even if a previous run crashed before
+/// saving the new `next_due`. The data stays locked from the first read to the last write, so
+/// items another instance changes meanwhile aren't overwritten. Returns the number of created
+/// bookings.
+pub fn run(storage: &dyn Storage, today: NaiveDate) -> Result<usize> {
+    let mut booked = 0;
+    storage.with_lock(&mut || {
+        booked = book_due(storage, today)?;
+        Ok(())
+    })?;
+    Ok(booked)
+}
+
+fn book_due(storage: &dyn Storage, today: NaiveDate) -> Result<usize> {
+    let mut recurring = storage.get_recurring()?;
+    let mut existing = storage.get_expenses()?;
+    existing.extend(storage.get_expenses_archive()?);
+
+    let mut booked = 0;
+    let mut changed = false;
+    for rec in recurring.iter_mut() {
+        if rec.anchor_date.is_none() {
+            // Items from before the scheduler existed start today, nothing is booked retroactively
+            rec.anchor_date = Some(today);
+            rec.next_due = rec.occurrence_after(today);
+            changed = true;
+            continue;
+        }
+
+        while let Some(due) = rec.next_due.filter(|d| *d <= today) {
+            let booking = materialize(rec, due);
+            let already_booked = existing.iter().any(|b| {
+                let same_item = match b.recurring_id {
+                    Some(id) => id == rec.id,
+                    // Older bookings only have the name to go by
+                    None => b.recurring && b.name == booking.name,
+                };
+                same_item && b.local_date() == due
+            });
+            if !already_booked {
+                storage.add_expense(booking)?;
+                booked += 1;
+            }
+            rec.next_due = rec.occurrence_after(due);
+            changed = true;
+        }
+    }
+
+    if changed {
+        storage.save_recurring(recurring)?;
+    }
+    Ok(booked)
+}
+
+fn materialize(rec: &RecurringBooking, due: NaiveDate) -> BudgetBooking {
+    let mut booking =
+        BudgetBooking::new(&rec.name, rec.kind.clone(), &rec.category_token, rec.amount)
+            .in_currency(&rec.currency)
+            .on_date(due);
+    booking.recurring = true;
+    booking.recurring_id = Some(rec.id);
+    booking
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+    use crate::{
+        commands::handle_command,
+        db::{JsonStorage, MemoryStorage},
+        model::{BookingType::*, Recurrence, RecurringType::*},
+        money::Money,
+    };
+    use chrono::{Days, Local, Months};
+    use std::{thread, time::Duration};
+
+    fn d(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_books_each_passed_occurrence_once() {
+        let storage = MemoryStorage::default();
+        let mut rent = RecurringBooking::new(
+            "Rent",
+            Expense,
+            "h",
+            Money::from_minor(80000),
+            Recurrence::new(Monthly, 1),
+        );
+        rent.anchor_date = Some(d(2026, 1, 31));
+        rent.next_due = Some(d(2026, 1, 31));
+        storage.add_recurring(rent).unwrap();
+
+        assert_eq!(run(&storage, d(2026, 4, 15)).unwrap(), 3);
+        let dates: Vec<NaiveDate> = storage
+            .get_expenses()
+            .unwrap()
+            .iter()
+            .map(|b| b.local_date())
+            .collect();
+        assert_eq!(dates, vec![d(2026, 1, 31), d(2026, 2, 28), d(2026, 3, 31)]);
+        assert!(storage.get_expenses().unwrap().iter().all(|b| b.recurring));
+        assert_eq!(
+            storage.get_recurring().unwrap()[0].next_due,
+            Some(d(2026, 4, 30))
+        );
+
+        assert_eq!(run(&storage, d(2026, 4, 15)).unwrap(), 0);
+
+        // a crash before next_due was saved must not book again
+        let mut recurring = storage.get_recurring().unwrap();
+        recurring[0].next_due = Some(d(2026, 3, 31));
+        storage.save_recurring(recurring).unwrap();
+        assert_eq!(run(&storage, d(2026, 4, 15)).unwrap(), 0);
+        assert_eq!(storage.get_expenses().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_bookings_are_matched_to_their_item() {
+        let storage = MemoryStorage::default();
+        for category in ["h", "s"] {
+            let mut gym = RecurringBooking::new(
+                "Gym",
+                Expense,
+                category,
+                Money::from_minor(3000),
+                Recurrence::new(Monthly, 1),
+            );
+            gym.anchor_date = Some(d(2026, 10, 1));
+            gym.next_due = Some(d(2026, 10, 1));
+            storage.add_recurring(gym).unwrap();
+        }
+        assert_eq!(run(&storage, d(2026, 10, 18)).unwrap(), 2);
+
+        // renamed after booking, and as if a crash had lost the new next_due
+        let mut recurring = storage.get_recurring().unwrap();
+        recurring[0].name = "Climbing".to_string();
+        recurring[0].next_due = Some(d(2026, 10, 1));
+        storage.save_recurring(recurring).unwrap();
+        assert_eq!(run(&storage, d(2026, 10, 18)).unwrap(), 0);
+        let ids: Vec<Option<u64>> = storage
+            .get_expenses()
+            .unwrap()
+            .iter()
+            .map(|b| b.recurring_id)
+            .collect();
+        assert_eq!(ids, vec![Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn test_occurrence_after_matches_a_full_scan() {
+        for (rate_type, interval) in [(Weekly, 2), (Monthly, 1), (Monthly, 3), (Yearly, 1)] {
+            let mut rec = RecurringBooking::new(
+                "Rent",
+                Expense,
+                "h",
+                Money::from_minor(100),
+                Recurrence::new(rate_type, interval),
+            );
+            let anchor = d(2024, 1, 31);
+            rec.anchor_date = Some(anchor);
+            let mut date = d(2023, 12, 1);
+            while date < d(2027, 3, 1) {
+                let scanned = (0..)
+                    .map(|n| rec.recurrence.nth_occurrence(anchor, n))
+                    .find(|o| o.is_none_or(|o| o > date))
+                    .flatten();
+                assert_eq!(rec.occurrence_after(date), scanned, "{}", date);
+                date = date.succ_opt().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_items_without_dates_start_today() {
+        let storage = MemoryStorage::default();
+        let mut legacy = RecurringBooking::new(
+            "Gym",
+            Expense,
+            "s",
+            Money::from_minor(3000),
+            Recurrence::new(Yearly, 1),
+        );
+        legacy.anchor_date = None;
+        legacy.next_due = None;
+        storage.add_recurring(legacy).unwrap();
+
+        assert_eq!(run(&storage, d(2026, 10, 18)).unwrap(), 0);
+        let rec = &storage.get_recurring().unwrap()[0];
+        assert_eq!(rec.anchor_date, Some(d(2026, 10, 18)));
+        assert_eq!(rec.next_due, Some(d(2027, 10, 18)));
+    }
+
+    #[test]
+    fn test_start_date_sets_the_due_day() {
+        let storage = MemoryStorage::default();
+        handle_command("ac Home h", &storage).unwrap();
+        let today = Local::now().date_naive();
+        let start = today - Days::new(17);
+        handle_command(&format!("are Rent h 800 start={}", start), &storage).unwrap();
+        let rent = storage.get_recurring().unwrap().remove(0);
+        let due = start + Months::new(1);
+        assert_eq!(rent.anchor_date, Some(start));
+        assert_eq!(rent.next_due, Some(due));
+
+        // nothing is due before the day the start date sets
+        assert_eq!(run(&storage, due.pred_opt().unwrap()).unwrap(), 0);
+        assert_eq!(run(&storage, due).unwrap(), 1);
+        assert_eq!(storage.get_expenses().unwrap()[0].local_date(), due);
+
+        // moving the start moves the following occurrences
+        let later = today + Days::new(3);
+        handle_command(&format!("er 1 start={}", later), &storage).unwrap();
+        let rent = storage.get_recurring().unwrap().remove(0);
+        assert_eq!(rent.next_due, Some(later));
+        assert!(handle_command("er 1 start=soon", &storage).is_err());
+    }
+
+    #[test]
+    fn test_other_writers_wait_for_the_run() {
+        let dir = TempDir::new("scheduler");
+        let storage = JsonStorage::new(&dir).unwrap();
+        let mut gym = RecurringBooking::new(
+            "Gym",
+            Expense,
+            "s",
+            Money::from_minor(3000),
+            Recurrence::new(Monthly, 1),
+        );
+        gym.anchor_date = Some(d(2026, 9, 1));
+        gym.next_due = Some(d(2026, 9, 1));
+        storage.add_recurring(gym).unwrap();
+
+        // another instance adds an item between the scheduler's read and write
+        let mut other = None;
+        storage
+            .with_lock(&mut || {
+                let mut recurring = storage.get_recurring()?;
+                let dir = dir.to_path_buf();
+                other = Some(thread::spawn(move || {
+                    let storage = JsonStorage::new(&dir).unwrap();
+                    storage
+                        .add_recurring(RecurringBooking::new(
+                            "Rent",
+                            Expense,
+                            "h",
+                            Money::from_minor(80000),
+                            Recurrence::new(Monthly, 1),
+                        ))
+                        .unwrap();
+                }));
+                thread::sleep(Duration::from_millis(300));
+                recurring[0].next_due = Some(d(2026, 11, 1));
+                storage.save_recurring(recurring)
+            })
+            .unwrap();
+        other.unwrap().join().unwrap();
+
+        let recurring = storage.get_recurring().unwrap();
+        assert_eq!(recurring.len(), 2);
+        assert_eq!(recurring[0].next_due, Some(d(2026, 11, 1)));
+        assert_eq!(recurring[1].name, "Rent");
+    }
+}
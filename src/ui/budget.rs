@@ -1,55 +1,215 @@
-use crate::model::BudgetBooking;
-use crate::ui::CURRENCY_SYMBOL;
+use crate::dataservice::{CategoryBudget, Envelope};
+use crate::model::{BookingType, BudgetBooking};
+use crate::money::Money;
 use anyhow::Result;
 use tui::layout::{Layout, Rect};
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction},
     style::{Color, Modifier, Style},
-    widgets::{Block, BorderType, Borders, Cell, Row, Table},
+    symbols,
+    widgets::{Block, BorderType, Borders, Cell, LineGauge, Row, Table},
     Frame,
 };
 
-use super::UserInterface;
+use super::{Selection, UserInterface};
 pub fn render<B: Backend>(f: &mut Frame<B>, chunk: Rect, app: &UserInterface) -> Result<()> {
     let budget_chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
         .split(chunk);
 
-    let bookings = app.dataservice.get_all_bookings().unwrap();
-    let table = render_budget(bookings);
+    // Limits and envelopes above the bookings of the period, if there are any
+    let budgets = &app.dataservice.category_budgets;
+    let envelopes: &[Envelope] = if app.dataservice.envelope_mode {
+        &app.dataservice.envelopes
+    } else {
+        &[]
+    };
+    let height = |rows: usize, extra: usize| if rows == 0 { 0 } else { (rows + extra) as u16 };
+    let left = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(height(budgets.len(), 2)),
+                Constraint::Length(height(envelopes.len(), 3)),
+                Constraint::Min(0),
+            ]
+            .as_ref(),
+        )
+        .split(budget_chunks[0]);
+    if !budgets.is_empty() {
+        render_limits(f, left[0], budgets, app);
+    }
+    if !envelopes.is_empty() {
+        f.render_widget(render_envelopes(envelopes, app), left[1]);
+    }
 
-    let booking_archive = app.dataservice.get_bookings_archive().unwrap();
-    let table2 = render_budget(booking_archive);
+    let bookings = app.dataservice.get_period_bookings()?;
+    let title = format!(" Bookings {} ", app.dataservice.period);
+    let table = render_budget(&bookings, title, app);
+    let mut state = app.table_state(0, bookings.len());
+    f.render_stateful_widget(table, left[2], &mut state);
 
-    f.render_widget(table, budget_chunks[0]);
-    f.render_widget(table2, budget_chunks[1]);
+    let booking_archive = app.dataservice.get_bookings_archive()?;
+    let table2 = render_budget(&booking_archive, " Archive ".to_string(), app);
+    let mut state = app.table_state(bookings.len(), booking_archive.len());
+    f.render_stateful_widget(table2, budget_chunks[1], &mut state);
     Ok(())
 }
 
-fn render_budget<'a>(items: &[BudgetBooking]) -> Table<'a> {
+/// A gauge per category limit, yellow from 80% and red once the limit is exceeded.
+fn render_limits<B: Backend>(
+    f: &mut Frame<B>,
+    chunk: Rect,
+    budgets: &[CategoryBudget],
+    app: &UserInterface,
+) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" Limits {} ", app.dataservice.period))
+        .border_type(BorderType::Plain);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Length(1); budgets.len()])
+        .split(block.inner(chunk));
+    f.render_widget(block, chunk);
+
+    let width = budgets.iter().map(|b| b.name.len()).max().unwrap_or(0);
+    for (budget, row) in budgets.iter().zip(rows) {
+        let color = if budget.is_exceeded() {
+            Color::Red
+        } else if budget.percent() >= 80 {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+        let left = if budget.is_exceeded() {
+            format!("{} over", -budget.remaining())
+        } else {
+            format!("{} left", budget.remaining())
+        };
+        let label = format!(
+            "{:width$} {} / {} {} ({}%, {}) ",
+            budget.name,
+            budget.spent,
+            budget.limit,
+            app.dataservice.currency_symbol,
+            budget.percent(),
+            left,
+            width = width
+        );
+        let gauge = LineGauge::default()
+            .gauge_style(Style::default().fg(color))
+            .line_set(symbols::line::THICK)
+            .label(label)
+            .ratio(budget.spent.ratio_of(budget.limit).clamp(0.0, 1.0));
+        f.render_widget(gauge, row);
+    }
+}
+
+/// What is left in each envelope, red once it is overspent.
+fn render_envelopes<'a>(envelopes: &[Envelope], app: &UserInterface) -> Table<'a> {
+    let rows: Vec<Row> = envelopes
+        .iter()
+        .map(|e| {
+            let style = if e.available() < Money::ZERO {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default()
+            };
+            let name = app
+                .dataservice
+                .category_name(&e.token)
+                .unwrap_or(&e.token)
+                .to_string();
+            Row::new(vec![
+                Cell::from(name),
+                Cell::from(e.carried.to_string()),
+                Cell::from(e.assigned.to_string()),
+                Cell::from(e.spent.to_string()),
+                Cell::from(e.available().to_string()),
+            ])
+            .style(style)
+        })
+        .collect();
+    Table::new(rows)
+        .style(Style::default().fg(Color::White))
+        .header(
+            Row::new(vec![
+                "Category",
+                "Carried",
+                "Assigned",
+                "Spent",
+                "Available",
+            ])
+            .style(Style::default().fg(Color::Yellow)),
+        )
+        .widths(&[
+            Constraint::Length(14),
+            Constraint::Length(9),
+            Constraint::Length(9),
+            Constraint::Length(9),
+            Constraint::Length(9),
+        ])
+        .column_spacing(1)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(
+                    " Envelopes {} ({}) ",
+                    app.dataservice.period, app.dataservice.currency_symbol
+                ))
+                .border_type(BorderType::Plain),
+        )
+}
+
+/// The rows that can be selected, the period's bookings followed by the archive.
+pub fn selectable(app: &UserInterface) -> Result<Vec<Selection>> {
+    Ok(app
+        .dataservice
+        .get_period_bookings()?
+        .into_iter()
+        .chain(app.dataservice.get_bookings_archive()?)
+        .map(|b| Selection::Booking(b.id))
+        .collect())
+}
+
+fn render_budget<'a>(items: &[&BudgetBooking], title: String, app: &UserInterface) -> Table<'a> {
     // active
     let items: Vec<_> = items
         .iter()
         .map(|b| {
+            // Income is money coming back into the budget
+            let (amount, style) = match b.kind {
+                BookingType::Income => (
+                    format!("+{}", app.format_amount(b.amount, &b.currency)),
+                    Style::default().fg(Color::Green),
+                ),
+                BookingType::Expense => {
+                    (app.format_amount(b.amount, &b.currency), Style::default())
+                }
+            };
             Row::new(vec![
+                Cell::from(b.id.to_string()),
                 Cell::from(b.name.to_string()),
-                Cell::from(format!("{:.2} {}", b.amount, *CURRENCY_SYMBOL)),
+                Cell::from(amount),
                 Cell::from(b.category_token.to_string()),
-                Cell::from(b.date.to_string()),
+                Cell::from(b.local_date().to_string()),
             ])
+            .style(style)
         })
         .collect();
     let t = Table::new(items)
         .style(Style::default().fg(Color::White))
         .header(
-            Row::new(vec!["Name", "Amount", "Category", "Date"])
+            Row::new(vec!["#", "Name", "Amount", "Category", "Date"])
                 .style(Style::default().fg(Color::Yellow)),
         )
         .widths(&[
+            Constraint::Length(4),
             Constraint::Length(10),
-            Constraint::Length(10),
+            Constraint::Length(20),
             Constraint::Length(10),
             Constraint::Length(10),
         ])
@@ -59,7 +219,7 @@ fn render_budget<'a>(items: &[BudgetBooking]) -> Table<'a> {
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title(" Expenses ")
+                .title(title)
                 .border_type(BorderType::Plain),
         );
     t
@@ -1,51 +1,319 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use tui::layout::{Layout, Rect};
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction},
     style::{Color, Modifier, Style},
-    widgets::{Block, BorderType, Borders, Cell, Row, Table},
+    text::{Span, Spans},
+    widgets::{Block, BorderType, Borders, Clear, Paragraph},
     Frame,
 };
 
-use crate::db;
+use crate::commands;
+use crate::db::Storage;
+use crate::model::Record;
 
-use super::UserInterface;
+use super::Selection;
 
-pub fn render<B: Backend>(f: &mut Frame<B>, chunk: Rect, _app: &UserInterface) -> Result<()> {
-    let setting_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(100)].as_ref())
-        .split(chunk);
-    let edit = render_edit_table();
-    f.render_widget(edit, setting_chunks[0]);
+/// Key of the field holding recurrence options like `every=3m count=4`.
+const RECURRENCE: &str = "recurrence";
 
-    Ok(())
+pub struct Field {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub value: String,
+    /// The value the form was opened with.
+    original: String,
+}
+
+impl Field {
+    fn new(key: &'static str, label: &'static str, value: &str) -> Self {
+        Field {
+            key,
+            label,
+            value: value.to_string(),
+            original: value.to_string(),
+        }
+    }
+
+    fn changed(&self) -> bool {
+        self.value.trim() != self.original
+    }
+}
+
+/// Editable copy of the selected row, written back through the edit commands on save.
+pub struct EditForm {
+    pub target: Selection,
+    pub title: String,
+    pub fields: Vec<Field>,
+    pub focus: usize,
+    pub error_message: String,
+}
+
+impl EditForm {
+    pub fn new(target: Selection, storage: &dyn Storage) -> Result<Self> {
+        let fields = match target {
+            Selection::Booking(id) => {
+                let mut bookings = storage.get_expenses()?;
+                bookings.extend(storage.get_expenses_archive()?);
+                let b = find(bookings, id)?;
+                vec![
+                    Field::new("name", "Name", &b.name),
+                    Field::new("amount", "Amount", &b.amount.to_string()),
+                    Field::new("category", "Category", &b.category_token),
+                    Field::new("date", "Date", &b.local_date().to_string()),
+                    Field::new("currency", "Currency", &b.currency),
+                ]
+            }
+            Selection::Recurring(id) => {
+                let r = find(storage.get_recurring()?, id)?;
+                vec![
+                    Field::new("name", "Name", &r.name),
+                    Field::new("amount", "Amount", &r.amount.to_string()),
+                    Field::new("category", "Category", &r.category_token),
+                    Field::new("currency", "Currency", &r.currency),
+                    Field::new(
+                        RECURRENCE,
+                        "Recurrence",
+                        &commands::recurrence_options(&r.recurrence),
+                    ),
+                    Field::new(
+                        "start",
+                        "Start",
+                        &r.anchor_date.map(|d| d.to_string()).unwrap_or_default(),
+                    ),
+                ]
+            }
+            Selection::Category(id) => {
+                let c = find(storage.get_categories()?, id)?;
+                vec![
+                    Field::new("name", "Name", &c.name),
+                    Field::new("token", "Token", &c.token),
+                    Field::new("parent", "Parent", c.parent.as_deref().unwrap_or_default()),
+                    Field::new(
+                        "limit",
+                        "Limit",
+                        &c.limit.map(|l| l.to_string()).unwrap_or_default(),
+                    ),
+                ]
+            }
+        };
+        Ok(EditForm {
+            target,
+            title: format!(" Edit {} ", target),
+            fields,
+            focus: 0,
+            error_message: String::new(),
+        })
+    }
+
+    pub fn next_field(&mut self) {
+        self.focus = (self.focus + 1) % self.fields.len();
+    }
+
+    pub fn previous_field(&mut self) {
+        self.focus = (self.focus + self.fields.len() - 1) % self.fields.len();
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.fields[self.focus].value.push(c);
+    }
+
+    pub fn pop(&mut self) {
+        self.fields[self.focus].value.pop();
+    }
+
+    /// Saves the changed fields through the matching edit command, e.g. `ee 4 name="Coffee beans"`.
+    /// Without changes nothing is run, so nothing ends up in the undo history.
+    pub fn save(&self, storage: &dyn Storage) -> Result<()> {
+        let changed: Vec<&Field> = self.fields.iter().filter(|f| f.changed()).collect();
+        if changed.is_empty() {
+            return Ok(());
+        }
+        let (command, id) = match self.target {
+            Selection::Booking(id) => ("ee", id),
+            Selection::Recurring(id) => ("er", id),
+            Selection::Category(id) => ("ec", id),
+        };
+        let mut line = format!("{} {}", command, id);
+        for field in changed {
+            if field.key == RECURRENCE {
+                line = format!("{} {}", line, field.value);
+                // Options left out keep their value, so removing one has to clear it
+                for key in ["until", "count"] {
+                    if has_option(&field.original, key) && !has_option(&field.value, key) {
+                        line = format!("{} {}=", line, key);
+                    }
+                }
+            } else {
+                let value = commands::quote(field.value.trim());
+                line = format!("{} {}={}", line, field.key, value);
+            }
+        }
+        commands::handle_command(&line, storage).map(|_| ())
+    }
+}
+
+fn has_option(recurrence: &str, key: &str) -> bool {
+    recurrence
+        .split_whitespace()
+        .any(|word| word.split_once('=').map(|(k, _)| k) == Some(key))
 }
 
-fn render_edit_table<'a>() -> Table<'a> {
-    let items: Vec<_> = db::get_settings()
-        .unwrap()
+fn find<T: Record>(items: Vec<T>, id: u64) -> Result<T> {
+    items
+        .into_iter()
+        .find(|i| i.id() == id)
+        .ok_or_else(|| anyhow!("Row #{} no longer exists", id))
+}
+
+pub fn render<B: Backend>(f: &mut Frame<B>, chunk: Rect, form: &EditForm) -> Result<()> {
+    let label_width = form.fields.iter().map(|f| f.label.len()).max().unwrap_or(0) + 2;
+    let mut text: Vec<Spans> = form
+        .fields
         .iter()
-        .map(|b| {
-            Row::new(vec![
-                Cell::from(b.key.to_string()),
-                Cell::from(b.value.to_string()),
+        .enumerate()
+        .map(|(i, field)| {
+            let style = if i == form.focus {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            Spans::from(vec![
+                Span::styled(
+                    format!("{:width$}", field.label, width = label_width),
+                    style,
+                ),
+                Span::styled(field.value.clone(), style),
             ])
         })
         .collect();
-    let t = Table::new(items)
-        .style(Style::default().fg(Color::White))
-        .header(Row::new(vec!["Key", "Value"]).style(Style::default().fg(Color::Yellow)))
-        .widths(&[Constraint::Percentage(50), Constraint::Percentage(50)])
-        .column_spacing(0)
-        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
-        .highlight_symbol(">>")
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(" Edit ")
-                .border_type(BorderType::Plain),
+    text.push(Spans::default());
+    if form.error_message.is_empty() {
+        text.push(Spans::from(Span::styled(
+            "Tab/Up/Down: switch field  Enter: save  Esc: cancel",
+            Style::default()
+                .add_modifier(Modifier::ITALIC)
+                .fg(Color::LightBlue),
+        )));
+    } else {
+        text.push(Spans::from(Span::styled(
+            form.error_message.clone(),
+            Style::default().fg(Color::Red),
+        )));
+    }
+
+    let area = popup_area(chunk, 50, text.len() as u16 + 2);
+    let popup = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(form.title.clone())
+            .border_type(BorderType::Thick)
+            .border_style(Style::default().fg(Color::Yellow)),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+
+    let value_len = form.fields[form.focus].value.chars().count() as u16;
+    f.set_cursor(
+        area.x + 1 + label_width as u16 + value_len,
+        area.y + 1 + form.focus as u16,
+    );
+    Ok(())
+}
+
+pub fn render_confirm_delete<B: Backend>(f: &mut Frame<B>, chunk: Rect, target: Selection) {
+    let text = vec![
+        Spans::from(Span::raw(format!("Delete {}?", target))),
+        Spans::default(),
+        Spans::from(Span::styled(
+            "y: delete  n/Esc: keep",
+            Style::default()
+                .add_modifier(Modifier::ITALIC)
+                .fg(Color::LightBlue),
+        )),
+    ];
+    let area = popup_area(chunk, 50, text.len() as u16 + 2);
+    let popup = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Delete ")
+            .border_type(BorderType::Thick)
+            .border_style(Style::default().fg(Color::LightRed)),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// A box of `height` lines and `width` percent of `chunk` centered in it.
+pub fn popup_area(chunk: Rect, width: u16, height: u16) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Min(0),
+                Constraint::Length(height),
+                Constraint::Min(0),
+            ]
+            .as_ref(),
+        )
+        .split(chunk);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage((100 - width) / 2),
+                Constraint::Percentage(width),
+                Constraint::Percentage((100 - width) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(vertical[1])[1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryStorage;
+    use crate::model::Category;
+
+    #[test]
+    fn test_save_sends_only_changes() {
+        let storage = MemoryStorage::default();
+        storage.add_category(Category::new("Food", "f")).unwrap();
+        commands::handle_command("ae Coffee f 3.50", &storage).unwrap();
+
+        let mut form = EditForm::new(Selection::Booking(1), &storage).unwrap();
+        form.save(&storage).unwrap();
+        assert_eq!(storage.get_journal().unwrap().undo.len(), 1);
+
+        // the booking changed since the form was opened, the form must not undo that
+        commands::handle_command("ee 1 name=Tea", &storage).unwrap();
+        form.focus = 1;
+        form.pop();
+        form.push('5');
+        form.save(&storage).unwrap();
+        let booking = &storage.get_expenses().unwrap()[0];
+        assert_eq!(
+            (booking.name.as_str(), booking.amount.to_string()),
+            ("Tea", "3.55".to_string())
         );
-    t
+    }
+
+    #[test]
+    fn test_save_clears_removed_options() {
+        let storage = MemoryStorage::default();
+        storage.add_category(Category::new("Food", "f")).unwrap();
+        commands::handle_command("are Gym f 30 every=4w until=2027-06-30 count=4", &storage)
+            .unwrap();
+
+        let mut form = EditForm::new(Selection::Recurring(1), &storage).unwrap();
+        form.focus = 4;
+        form.fields[4].value = "every=4w count=4".to_string();
+        form.save(&storage).unwrap();
+        let recurrence = &storage.get_recurring().unwrap()[0].recurrence;
+        assert_eq!((recurrence.until, recurrence.count), (None, Some(4)));
+    }
 }
@@ -0,0 +1,33 @@
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, BorderType, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use super::edit::popup_area;
+
+/// Shows the output of the `help` command until a key is pressed.
+pub fn render<B: Backend>(f: &mut Frame<B>, chunk: Rect, page: &str) {
+    let mut text: Vec<Spans> = page.lines().map(Spans::from).collect();
+    text.push(Spans::default());
+    text.push(Spans::from(Span::styled(
+        "Press any key to close",
+        Style::default()
+            .add_modifier(Modifier::ITALIC)
+            .fg(Color::LightBlue),
+    )));
+
+    let area = popup_area(chunk, 80, text.len() as u16 + 2);
+    let popup = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Help ")
+            .border_type(BorderType::Thick)
+            .border_style(Style::default().fg(Color::LightBlue)),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
@@ -0,0 +1,204 @@
+use std::{fs, io::ErrorKind, path::PathBuf};
+
+use anyhow::Result;
+
+/// Only the most recent commands are kept.
+const MAX_ENTRIES: usize = 1000;
+
+/// Previously entered commands, oldest first, saved to one line per command.
+#[derive(Default)]
+pub struct History {
+    entries: Vec<String>,
+    /// `None` keeps the history for this session only, e.g. in read-only mode.
+    path: Option<PathBuf>,
+    /// Entry shown while browsing with Up/Down.
+    browsing: Option<usize>,
+    /// The command being typed before browsing started.
+    draft: String,
+    pub search: Option<Search>,
+}
+
+/// State of the incremental reverse search started with Ctrl-R.
+pub struct Search {
+    pub query: String,
+    /// Index of the most recent entry containing the query.
+    found: Option<usize>,
+    /// The command before the search, restored on cancel.
+    before: String,
+}
+
+impl History {
+    pub fn load(path: Option<PathBuf>) -> Result<Self> {
+        let entries = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => contents.lines().map(|l| l.to_string()).collect(),
+                Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+                Err(e) => return Err(e.into()),
+            },
+            None => vec![],
+        };
+        Ok(History {
+            entries,
+            path,
+            ..Default::default()
+        })
+    }
+
+    /// Records an entered command, skipping blanks and repeats of the last one.
+    pub fn push(&mut self, command: &str) -> Result<()> {
+        self.reset();
+        let command = command.trim();
+        if command.is_empty() || self.entries.last().is_some_and(|l| l == command) {
+            return Ok(());
+        }
+        self.entries.push(command.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+        match &self.path {
+            Some(path) => Ok(fs::write(path, self.entries.join("\n") + "\n")?),
+            None => Ok(()),
+        }
+    }
+
+    /// Starts browsing from the most recent entry again.
+    pub fn reset(&mut self) {
+        self.browsing = None;
+        self.search = None;
+    }
+
+    /// The entry before the one shown, starting with the most recent.
+    pub fn previous(&mut self, current: &str) -> Option<&str> {
+        let index = match self.browsing {
+            None => {
+                self.draft = current.to_string();
+                self.entries.len().checked_sub(1)?
+            }
+            Some(i) => i.saturating_sub(1),
+        };
+        self.browsing = Some(index);
+        Some(&self.entries[index])
+    }
+
+    /// The entry after the one shown, and finally the command typed before browsing.
+    pub fn next(&mut self) -> Option<&str> {
+        let index = self.browsing? + 1;
+        if index < self.entries.len() {
+            self.browsing = Some(index);
+            Some(&self.entries[index])
+        } else {
+            self.browsing = None;
+            Some(&self.draft)
+        }
+    }
+
+    pub fn start_search(&mut self, current: &str) {
+        self.search = Some(Search {
+            query: String::new(),
+            found: None,
+            before: current.to_string(),
+        });
+    }
+
+    pub fn search_push(&mut self, c: char) {
+        if let Some(search) = self.search.as_mut() {
+            search.query.push(c);
+        }
+        self.find_from(self.entries.len());
+    }
+
+    pub fn search_pop(&mut self) {
+        if let Some(search) = self.search.as_mut() {
+            search.query.pop();
+        }
+        self.find_from(self.entries.len());
+    }
+
+    /// Moves on to an older match, like pressing Ctrl-R again in a shell.
+    pub fn search_older(&mut self) {
+        if let Some(found) = self.search.as_ref().and_then(|s| s.found) {
+            self.find_from(found);
+        }
+    }
+
+    /// Looks for the query in the entries before `end`, keeping the current match if there is
+    /// no other.
+    fn find_from(&mut self, end: usize) {
+        if let Some(search) = self.search.as_mut() {
+            if search.query.is_empty() {
+                search.found = None;
+                return;
+            }
+            let found = self.entries[..end]
+                .iter()
+                .rposition(|e| e.contains(&search.query));
+            if found.is_some() || end == self.entries.len() {
+                search.found = found;
+            }
+        }
+    }
+
+    pub fn search_match(&self) -> Option<&str> {
+        let found = self.search.as_ref()?.found?;
+        Some(&self.entries[found])
+    }
+
+    /// Ends the search with its match, or the command before it if cancelled or nothing matched.
+    pub fn end_search(&mut self, accept: bool) -> String {
+        let matched = self.search_match().map(|m| m.to_string());
+        let search = self.search.take();
+        match (accept, matched) {
+            (true, Some(matched)) => matched,
+            _ => search.map(|s| s.before).unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_browse_and_search() {
+        let dir = TempDir::new("history");
+        let path = dir.join("data_command_history.txt");
+        let mut history = History::load(Some(path.clone())).unwrap();
+        for command in [
+            "ae Coffee f 3",
+            "ae Lunch f 9",
+            "ae Lunch f 9",
+            " ",
+            "are Gym f 30",
+        ] {
+            history.push(command).unwrap();
+        }
+
+        let mut history = History::load(Some(path.clone())).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(history.previous("ae Te"), Some("are Gym f 30"));
+        assert_eq!(history.previous(""), Some("ae Lunch f 9"));
+        assert_eq!(history.previous(""), Some("ae Coffee f 3"));
+        assert_eq!(history.previous(""), Some("ae Coffee f 3"));
+        assert_eq!(history.next(), Some("ae Lunch f 9"));
+        assert_eq!(history.next(), Some("are Gym f 30"));
+        assert_eq!(history.next(), Some("ae Te"));
+        assert_eq!(history.next(), None);
+
+        history.start_search("ae Te");
+        history.search_push('a');
+        assert_eq!(history.search_match(), Some("are Gym f 30"));
+        history.search_push('e');
+        assert_eq!(history.search_match(), Some("ae Lunch f 9"));
+        history.search_older();
+        assert_eq!(history.search_match(), Some("ae Coffee f 3"));
+        history.search_older();
+        assert_eq!(history.search_match(), Some("ae Coffee f 3"));
+        assert_eq!(history.end_search(true), "ae Coffee f 3");
+
+        history.start_search("ae Te");
+        history.search_push('x');
+        assert_eq!(history.search_match(), None);
+        assert_eq!(history.end_search(true), "ae Te");
+    }
+}
@@ -1,34 +1,33 @@
 mod budget;
 mod edit;
+mod help;
+mod history;
 mod planning;
 mod settings;
-use crate::dataservice::DataService;
-use crate::db;
+use crate::dataservice::{DataService, Envelope};
+use crate::db::Storage;
+use crate::money::Money;
 use anyhow::Result;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use lazy_static::lazy_static;
-use std::{collections::HashMap, io};
+use std::{collections::HashSet, fmt::Display, io, path::PathBuf};
 use tui::layout::Layout;
 use tui::{
     backend::{Backend, CrosstermBackend},
     layout::{Alignment, Constraint, Direction},
     style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{Block, BorderType, Borders, Paragraph, Tabs},
+    widgets::{Block, BorderType, Borders, Paragraph, TableState, Tabs},
     Frame, Terminal,
 };
 
 use crate::commands;
-
-lazy_static! {
-    pub static ref CURRENCY_SYMBOL: String = db::get_setting_currency_symbol().unwrap();
-    pub static ref CATEGORY_TOKEN_MAP: HashMap<String, String> =
-        DataService::new().get_categorie_map().unwrap();
-}
+use crate::journal;
 
 #[derive(Default, PartialEq)]
 pub enum UIMode {
@@ -36,6 +35,36 @@ pub enum UIMode {
     Normal,
     Command,
     Edit,
+    ConfirmDelete,
+    Help,
+}
+
+/// A row of one of the tables that can be selected, edited and deleted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
+    Booking(u64),
+    Recurring(u64),
+    Category(u64),
+}
+
+impl Selection {
+    fn delete_command(&self) -> String {
+        match self {
+            Selection::Booking(id) => format!("de {}", id),
+            Selection::Recurring(id) => format!("dr {}", id),
+            Selection::Category(id) => format!("dc {}", id),
+        }
+    }
+}
+
+impl Display for Selection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Selection::Booking(id) => write!(f, "booking #{}", id),
+            Selection::Recurring(id) => write!(f, "recurring item #{}", id),
+            Selection::Category(id) => write!(f, "category #{}", id),
+        }
+    }
 }
 
 pub struct UserInterface<'a> {
@@ -43,36 +72,250 @@ pub struct UserInterface<'a> {
     pub index: usize,
     pub mode: UIMode,
     pub error_message: String,
+    /// Why the data couldn't be loaded or calculated, e.g. a missing exchange rate.
+    data_error: String,
     command: String,
+    /// Index into the selectable rows of the current tab.
+    selected: usize,
+    form: Option<edit::EditForm>,
+    /// Page shown by the `help` command.
+    help: String,
+    /// Candidates for the last word of the command.
+    completion: commands::Completion,
+    /// The candidate Tab last put into the command.
+    completion_index: Option<usize>,
+    history: history::History,
+    /// Tokens of the subcategories shown without their items on the Planning tab.
+    collapsed: HashSet<String>,
     pub dataservice: DataService,
+    pub storage: Box<dyn Storage>,
 }
 
 impl<'a> UserInterface<'a> {
-    fn new() -> UserInterface<'a> {
-        UserInterface {
+    fn new(storage: Box<dyn Storage>, history_path: Option<PathBuf>) -> Result<UserInterface<'a>> {
+        let dataservice = DataService::new(storage.as_ref())?;
+        let mut app = UserInterface {
             tabs: vec!["Planning", "Budget", "Settings"],
             index: 0,
             mode: UIMode::default(),
             command: String::new(),
+            selected: 0,
+            form: None,
+            help: String::new(),
+            completion: commands::Completion::default(),
+            completion_index: None,
+            history: history::History::load(history_path)?,
+            collapsed: HashSet::new(),
             error_message: String::new(),
-            dataservice: DataService::new(),
+            data_error: String::new(),
+            dataservice,
+            storage,
+        };
+        app.refresh();
+        Ok(app)
+    }
+
+    /// Reloads the data and recalculates the totals. Errors are shown instead of ending the UI,
+    /// as they usually need a fix in the settings or data files, e.g. an exchange rate.
+    fn refresh(&mut self) {
+        let result = self
+            .dataservice
+            .load_data(self.storage.as_ref())
+            .and_then(|_| self.dataservice.calculate());
+        self.data_error = match result {
+            Ok(()) => String::new(),
+            Err(e) => e.to_string(),
+        };
+    }
+
+    /// Formats an amount with its currency and, for foreign currencies, the converted amount.
+    pub fn format_amount(&self, amount: Money, currency: &str) -> String {
+        if self.dataservice.is_base_currency(currency) {
+            return format!("{} {}", amount, self.dataservice.currency_symbol);
+        }
+        match self.dataservice.in_base(amount, currency) {
+            Ok(converted) => format!(
+                "{} {} ({} {})",
+                amount, currency, converted, self.dataservice.currency_symbol
+            ),
+            Err(_) => format!("{} {}", amount, currency),
         }
     }
 
     pub fn next(&mut self) {
-        self.index = (self.index + 1) % self.tabs.len();
+        self.select_tab((self.index + 1) % self.tabs.len());
     }
 
     pub fn previous(&mut self) {
         if self.index > 0 {
-            self.index -= 1;
+            self.select_tab(self.index - 1);
+        } else {
+            self.select_tab(self.tabs.len() - 1);
+        }
+    }
+
+    fn select_tab(&mut self, index: usize) {
+        self.index = index;
+        self.selected = 0;
+    }
+
+    /// The rows of the current tab in the order they are selected with j/k.
+    fn selectable(&self) -> Result<Vec<Selection>> {
+        match self.index {
+            0 => planning::selectable(self),
+            1 => budget::selectable(self),
+            2 => settings::selectable(self),
+            _ => Ok(vec![]),
+        }
+    }
+
+    fn selection(&self) -> Option<Selection> {
+        self.selectable().ok()?.get(self.selected).copied()
+    }
+
+    /// Whether bookings or recurring items refer to the selected category.
+    fn in_use(&self, target: Selection) -> bool {
+        let id = match target {
+            Selection::Category(id) => id,
+            _ => return false,
+        };
+        let categories = self.storage.get_categories().unwrap_or_default();
+        match categories.iter().find(|c| c.id == id) {
+            Some(category) => commands::category_usage(&category.token, self.storage.as_ref())
+                .is_ok_and(|(bookings, recurring)| !bookings.is_empty() || !recurring.is_empty()),
+            None => false,
+        }
+    }
+
+    fn select_next(&mut self) {
+        let len = self.selectable().map(|s| s.len()).unwrap_or(0);
+        if self.selected + 1 < len {
+            self.selected += 1;
+        }
+    }
+
+    fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Keeps the selection in range after rows were deleted or the period changed.
+    fn clamp_selection(&mut self) {
+        let len = self.selectable().map(|s| s.len()).unwrap_or(0);
+        self.selected = self.selected.min(len.saturating_sub(1));
+    }
+
+    /// State for a table showing the selectable rows `offset..offset + len`.
+    pub fn table_state(&self, offset: usize, len: usize) -> TableState {
+        let mut state = TableState::default();
+        if (offset..offset + len).contains(&self.selected) {
+            state.select(Some(self.selected - offset));
+        }
+        state
+    }
+
+    /// Shows a command from the history.
+    fn recall(&mut self, command: Option<String>) {
+        if let Some(command) = command {
+            self.command = command;
+            self.update_completion();
+        }
+    }
+
+    /// Keys while searching the history with Ctrl-R.
+    fn search_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.history.search_older()
+            }
+            KeyCode::Char(c) => self.history.search_push(c),
+            KeyCode::Backspace => self.history.search_pop(),
+            KeyCode::Esc => {
+                let before = self.history.end_search(false);
+                self.recall(Some(before));
+            }
+            _ => {
+                let found = self.history.end_search(true);
+                self.recall(Some(found));
+            }
+        }
+    }
+
+    /// Recomputes the completion after the command was edited.
+    fn update_completion(&mut self) {
+        self.completion =
+            commands::complete(&self.command, self.storage.as_ref()).unwrap_or_default();
+        self.completion_index = None;
+    }
+
+    /// Replaces the last word with the next (or previous) candidate. A single candidate is
+    /// accepted right away so completion continues with the next argument.
+    fn cycle_completion(&mut self, forward: bool) {
+        let len = self.completion.candidates.len();
+        if len == 0 {
+            return;
+        }
+        let index = match (self.completion_index, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(i), true) => (i + 1) % len,
+            (Some(i), false) => (i + len - 1) % len,
+        };
+        let candidate = &self.completion.candidates[index];
+        self.command = format!("{}{}", &self.command[..self.completion.start], candidate);
+        if len == 1 && !candidate.ends_with('=') {
+            self.command.push(' ');
+            self.update_completion();
         } else {
-            self.index = self.tabs.len() - 1;
+            self.completion_index = Some(index);
+        }
+    }
+
+    /// The rest of the first candidate after what was typed, shown dimmed after the cursor.
+    fn ghost_text(&self) -> &str {
+        if self.completion_index.is_some() {
+            return "";
+        }
+        let typed = &self.command[self.completion.start..];
+        self.completion
+            .candidates
+            .first()
+            .and_then(|c| c.strip_prefix(typed))
+            .unwrap_or_default()
+    }
+
+    /// Shows or hides what is below the selected subcategory on the Planning tab.
+    fn toggle_collapsed(&mut self) {
+        if let Some(Selection::Category(id)) = self.selection() {
+            let category = self
+                .storage
+                .get_categories()
+                .ok()
+                .and_then(|categories| categories.into_iter().find(|c| c.id == id));
+            if let Some(category) = category {
+                if !self.collapsed.remove(&category.token) {
+                    self.collapsed.insert(category.token);
+                }
+            }
+        }
+    }
+
+    fn open_form(&mut self) {
+        if let Some(target) = self.selection() {
+            match edit::EditForm::new(target, self.storage.as_ref()) {
+                Ok(form) => {
+                    self.form = Some(form);
+                    self.mode = UIMode::Edit;
+                }
+                Err(e) => self.error_message = e.to_string(),
+            }
         }
     }
 }
 
-pub fn draw() -> Result<()> {
+/// Runs the UI, keeping the command history in `history_path` if given.
+pub fn draw(storage: Box<dyn Storage>, history_path: Option<PathBuf>) -> Result<()> {
+    let ui = UserInterface::new(storage, history_path)?;
+
     // setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -80,8 +323,7 @@ pub fn draw() -> Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // create ui and run it
-    let ui = UserInterface::new();
+    // run ui
     let res = run_ui(&mut terminal, ui);
 
     // restore terminal
@@ -108,18 +350,42 @@ fn run_ui<B: Backend>(terminal: &mut Terminal<B>, mut app: UserInterface) -> Res
             match app.mode {
                 UIMode::Normal => match key.code {
                     KeyCode::Char('q') => return Ok(()),
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.error_message = match journal::redo(app.storage.as_ref()) {
+                            Ok(_) => String::new(),
+                            Err(e) => e.to_string(),
+                        };
+                    }
+                    KeyCode::Char('u') => {
+                        app.error_message = match journal::undo(app.storage.as_ref()) {
+                            Ok(_) => String::new(),
+                            Err(e) => e.to_string(),
+                        };
+                    }
                     KeyCode::Right => app.next(),
                     KeyCode::Left => app.previous(),
-                    KeyCode::Char('p') => app.index = 0,
-                    KeyCode::Char('b') => app.index = 1,
-                    KeyCode::Char('s') => app.index = 2,
-                    KeyCode::Char('e') => app.mode = UIMode::Edit,
+                    KeyCode::Char('p') => app.select_tab(0),
+                    KeyCode::Char('b') => app.select_tab(1),
+                    KeyCode::Char('s') => app.select_tab(2),
+                    KeyCode::Down | KeyCode::Char('j') => app.select_next(),
+                    KeyCode::Up | KeyCode::Char('k') => app.select_previous(),
+                    KeyCode::Enter | KeyCode::Char('e') => app.open_form(),
+                    KeyCode::Char(' ') if app.index == 0 => app.toggle_collapsed(),
+                    KeyCode::Char('d') if app.selection().is_some() => {
+                        app.mode = UIMode::ConfirmDelete;
+                    }
+                    KeyCode::Char('[') => app.dataservice.previous_period(),
+                    KeyCode::Char(']') => app.dataservice.next_period(),
+                    KeyCode::Char('t') => app.dataservice.current_period(),
                     KeyCode::Char(':') | KeyCode::Char('c') => {
                         app.mode = UIMode::Command;
                         app.error_message = String::new();
+                        app.history.reset();
+                        app.update_completion();
                     }
                     _ => {}
                 },
+                UIMode::Command if app.history.search.is_some() => app.search_key(key),
                 UIMode::Command => match key.code {
                     KeyCode::Esc => {
                         app.mode = UIMode::Normal;
@@ -128,32 +394,91 @@ fn run_ui<B: Backend>(terminal: &mut Terminal<B>, mut app: UserInterface) -> Res
 
                     KeyCode::Enter => {
                         app.mode = UIMode::Normal;
-                        match commands::handle_command(&app.command) {
-                            Ok(_) => {}
-                            Err(_) => app.error_message = "Invalid Command".to_string(),
+                        if let Err(e) = app.history.push(&app.command) {
+                            app.error_message = format!("Unable to save command history: {}", e);
+                        }
+                        match commands::handle_command(&app.command, app.storage.as_ref()) {
+                            Ok(Some(page)) => {
+                                app.help = page;
+                                app.mode = UIMode::Help;
+                            }
+                            Ok(None) => {}
+                            Err(e) => app.error_message = e.to_string(),
                         };
                         app.command = String::new();
                     }
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.history.start_search(&app.command);
+                    }
+                    KeyCode::Up => {
+                        let previous = app.history.previous(&app.command).map(String::from);
+                        app.recall(previous);
+                    }
+                    KeyCode::Down => {
+                        let next = app.history.next().map(String::from);
+                        app.recall(next);
+                    }
+                    KeyCode::Tab => app.cycle_completion(true),
+                    KeyCode::BackTab => app.cycle_completion(false),
                     KeyCode::Char(c) => {
                         app.command.push(c);
+                        app.update_completion();
                     }
                     KeyCode::Backspace => {
                         app.command.pop();
+                        app.update_completion();
                     }
                     _ => {}
                 },
-                UIMode::Edit => match key.code {
-                    KeyCode::Esc => {
+                UIMode::Edit => {
+                    if let Some(form) = app.form.as_mut() {
+                        match key.code {
+                            KeyCode::Esc => {
+                                app.mode = UIMode::Normal;
+                                app.form = None;
+                            }
+                            KeyCode::Enter => match form.save(app.storage.as_ref()) {
+                                Ok(_) => {
+                                    app.mode = UIMode::Normal;
+                                    app.form = None;
+                                }
+                                Err(e) => form.error_message = e.to_string(),
+                            },
+                            KeyCode::Tab | KeyCode::Down => form.next_field(),
+                            KeyCode::BackTab | KeyCode::Up => form.previous_field(),
+                            KeyCode::Char(c) => form.push(c),
+                            KeyCode::Backspace => form.pop(),
+                            _ => {}
+                        }
+                    } else {
                         app.mode = UIMode::Normal;
-                        app.command = String::new();
                     }
-                    KeyCode::Down => {}
+                }
+                UIMode::ConfirmDelete => match key.code {
+                    KeyCode::Char('y') => {
+                        app.mode = UIMode::Normal;
+                        if let Some(target) = app.selection() {
+                            let cmd = target.delete_command();
+                            if let Err(e) = commands::handle_command(&cmd, app.storage.as_ref()) {
+                                app.error_message = e.to_string();
+                                // A category in use needs a category to move its bookings to
+                                if app.in_use(target) {
+                                    app.command = format!("{} to=", cmd);
+                                    app.mode = UIMode::Command;
+                                    app.history.reset();
+                                    app.update_completion();
+                                }
+                            }
+                        }
+                    }
+                    KeyCode::Char('n') | KeyCode::Esc => app.mode = UIMode::Normal,
                     _ => {}
                 },
+                UIMode::Help => app.mode = UIMode::Normal,
             }
         }
-        app.dataservice.load_data()?;
-        app.dataservice.calculate()?;
+        app.refresh();
+        app.clamp_selection();
     }
 }
 
@@ -162,7 +487,7 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &UserInterface) -> Result<()> {
 
     let mut cmd_box_size = 3;
     if app.mode == UIMode::Command {
-        cmd_box_size = 4;
+        cmd_box_size = 5;
     }
 
     let chunks = Layout::default()
@@ -179,13 +504,14 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &UserInterface) -> Result<()> {
 
     // Bottom - Overview/Command
     match app.mode {
-        UIMode::Normal | UIMode::Edit => {
+        UIMode::Normal | UIMode::Edit | UIMode::ConfirmDelete | UIMode::Help => {
             let bottom: Paragraph = get_overview(app);
             f.render_widget(bottom, chunks[2]);
         }
         UIMode::Command => {
             let input = get_command(app);
-            f.set_cursor(chunks[2].x + app.command.len() as u16 + 1, chunks[2].y + 1);
+            let width = input_line(app).width() as u16;
+            f.set_cursor(chunks[2].x + width + 1, chunks[2].y + 1);
             f.render_widget(input, chunks[2]);
         }
     }
@@ -194,35 +520,49 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &UserInterface) -> Result<()> {
     let tabs: Tabs = get_tab_menu(app);
     f.render_widget(tabs, chunks[0]);
 
-    // Edit popup
-    if app.mode == UIMode::Edit {
-        edit::render(f, chunks[1], app)?;
-    } else {
-        // Content
-        match app.index {
-            0 => planning::render(f, chunks[1], app)?,
-            1 => budget::render(f, chunks[1], app)?,
-            2 => settings::render(f, chunks[1], app)?,
-            _ => {}
+    // Content
+    let content = match app.index {
+        0 => planning::render(f, chunks[1], app),
+        1 => budget::render(f, chunks[1], app),
+        2 => settings::render(f, chunks[1], app),
+        _ => Ok(()),
+    };
+    if let Err(e) = content {
+        let error = Paragraph::new(e.to_string())
+            .style(Style::default().fg(Color::Red))
+            .block(Block::default().borders(Borders::ALL));
+        f.render_widget(error, chunks[1]);
+    }
+
+    // Popups
+    match (&app.mode, &app.form) {
+        (UIMode::Edit, Some(form)) => edit::render(f, chunks[1], form)?,
+        (UIMode::ConfirmDelete, _) => {
+            if let Some(target) = app.selection() {
+                edit::render_confirm_delete(f, chunks[1], target);
+            }
         }
+        (UIMode::Help, _) => help::render(f, chunks[1], &app.help),
+        _ => {}
     }
     Ok(())
 }
 
-fn get_command_help_text(app: &str) -> String {
-    // TODO: add help commands and long commands
-
-    if app.starts_with("are") {
-        "Add-Recurring-Expense Syntax: <Name> <Category-Token> <Amount> (<Yearly>)".to_string()
-    } else if app.starts_with("ari") {
-        "Add-Recurring-Income Syntax: <Name> <Category-Token> <Amount>".to_string()
-    } else if app.starts_with("ae") {
-        "Add-Expense Syntax: <Name> <Category-Token> <Amount>".to_string()
-    } else if app.starts_with("ac") {
-        "Add-Category Syntax: <Name> <Category-Token>".to_string()
-    } else {
-        "Commands: add-expense | add-recurring-expense | add-recurring-income | add-categorie"
-            .to_string()
+/// Usage of the command being typed, or all commands while there is none.
+fn get_command_help_text(input: &str) -> String {
+    let word = input.split_whitespace().next().unwrap_or_default();
+    match commands::find_command(word) {
+        Some(command) => format!("{}: {}", command.usage(), command.help),
+        None => {
+            let names: Vec<&str> = commands::COMMANDS
+                .iter()
+                .map(|c| c.aliases.first().copied().unwrap_or(c.name))
+                .collect();
+            format!(
+                "Commands: {}  (help <command> for details)",
+                names.join(" | ")
+            )
+        }
     }
 }
 
@@ -255,66 +595,164 @@ fn get_tab_menu<'a>(app: &UserInterface<'a>) -> Tabs<'a> {
 }
 
 fn get_overview<'a>(app: &'a UserInterface) -> Paragraph<'a> {
-    let mut text = Spans::from(vec![
-        Span::styled(
-            format!(
-                "  Income: {:.2} {}",
-                app.dataservice.total_income, *CURRENCY_SYMBOL
+    let mut text = if app.dataservice.envelope_mode {
+        envelope_overview(app)
+    } else {
+        Spans::from(vec![
+            Span::styled(
+                format!(
+                    "  Income: {} {}",
+                    app.dataservice.total_income, app.dataservice.currency_symbol
+                ),
+                Style::default().fg(Color::LightGreen),
             ),
-            Style::default().fg(Color::LightGreen),
-        ),
-        Span::styled(
-            format!(
-                "  Expenses: {:.2} {}",
-                app.dataservice.total_reccuring_expenses, *CURRENCY_SYMBOL
+            Span::styled(
+                format!(
+                    "  Expenses: {} {}",
+                    app.dataservice.total_reccuring_expenses, app.dataservice.currency_symbol
+                ),
+                Style::default().fg(Color::LightRed),
             ),
-            Style::default().fg(Color::LightRed),
-        ),
-        Span::styled(
-            format!(
-                "  Budget Spent: {:.2} {}",
-                app.dataservice.total_budget_spent, *CURRENCY_SYMBOL
+            Span::styled(
+                format!(
+                    "  Budget Spent: {} {}",
+                    app.dataservice.total_budget_spent, app.dataservice.currency_symbol
+                ),
+                Style::default().fg(Color::LightMagenta),
             ),
-            Style::default().fg(Color::LightMagenta),
-        ),
-        Span::styled(
-            format!(
-                "  Budget left: {:.2} {}",
-                app.dataservice.total_budget_left, *CURRENCY_SYMBOL
+            Span::styled(
+                format!(
+                    "  Budget left: {} {}",
+                    app.dataservice.total_budget_left, app.dataservice.currency_symbol
+                ),
+                Style::default().fg(Color::Green),
             ),
-            Style::default().fg(Color::Green),
-        ),
-    ]);
+        ])
+    };
 
-    if !app.error_message.is_empty() {
-        text = Spans::from(Span::styled(
-            &app.error_message,
-            Style::default().fg(Color::Red),
+    let exceeded: Vec<&str> = app
+        .dataservice
+        .category_budgets
+        .iter()
+        .filter(|b| b.is_exceeded())
+        .map(|b| b.name.as_str())
+        .collect();
+    if !exceeded.is_empty() {
+        text.0.push(Span::styled(
+            format!("  Over limit: {}", exceeded.join(", ")),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
         ));
     }
 
+    let error = [&app.error_message, &app.data_error]
+        .into_iter()
+        .find(|e| !e.is_empty());
+    if let Some(error) = error {
+        text = Spans::from(Span::styled(error, Style::default().fg(Color::Red)));
+    }
+
     let bottom = Paragraph::new(text).alignment(Alignment::Center).block(
         Block::default()
             .borders(Borders::ALL)
             .style(Style::default().fg(Color::White))
-            .title(" Overview / Command ")
+            .title(format!(
+                " Overview {} ([ ] to change, t for today, u undo, ^r redo) / Command ",
+                app.dataservice.period
+            ))
             .border_type(BorderType::Thick),
     );
     bottom
 }
 
-fn get_command<'a>(app: &'a UserInterface) -> Paragraph<'a> {
-    let text = vec![
-        Spans::from(Span::styled(
+/// Totals of the envelopes instead of the planned ones, and the income left to assign.
+fn envelope_overview<'a>(app: &'a UserInterface) -> Spans<'a> {
+    let ds = &app.dataservice;
+    let sum = |f: fn(&Envelope) -> Money| ds.envelopes.iter().map(f).sum::<Money>();
+    let unassigned = if ds.total_unassigned < Money::ZERO {
+        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(Color::LightGreen)
+    };
+    Spans::from(vec![
+        Span::styled(
+            format!(
+                "  Unassigned: {} {}",
+                ds.total_unassigned, ds.currency_symbol
+            ),
+            unassigned,
+        ),
+        Span::styled(
+            format!("  Assigned: {} {}", sum(|e| e.assigned), ds.currency_symbol),
+            Style::default().fg(Color::LightBlue),
+        ),
+        Span::styled(
+            format!("  Spent: {} {}", sum(|e| e.spent), ds.currency_symbol),
+            Style::default().fg(Color::LightMagenta),
+        ),
+        Span::styled(
+            format!(
+                "  Available: {} {}",
+                sum(Envelope::available),
+                ds.currency_symbol
+            ),
+            Style::default().fg(Color::Green),
+        ),
+    ])
+}
+
+/// The command being typed up to the cursor, or the state of the history search.
+fn input_line<'a>(app: &'a UserInterface) -> Spans<'a> {
+    match &app.history.search {
+        Some(search) => Spans::from(vec![
+            Span::styled(
+                format!("(reverse-i-search)'{}': ", search.query),
+                Style::default().fg(Color::LightBlue),
+            ),
+            Span::styled(
+                app.history.search_match().unwrap_or_default(),
+                Style::default().fg(Color::White),
+            ),
+        ]),
+        None => Spans::from(Span::styled(
             &app.command,
             Style::default().fg(Color::White),
         )),
+    }
+}
+
+fn get_command<'a>(app: &'a UserInterface) -> Paragraph<'a> {
+    let mut candidates = vec![];
+    if !app.completion.candidates.is_empty() {
+        candidates.push(Span::styled("Tab:", Style::default().fg(Color::DarkGray)));
+    }
+    for (i, candidate) in app.completion.candidates.iter().enumerate() {
+        let style = if app.completion_index == Some(i) {
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::Gray)
+        };
+        candidates.push(Span::raw(" "));
+        candidates.push(Span::styled(candidate.as_str(), style));
+    }
+
+    let mut input = input_line(app);
+    if app.history.search.is_none() {
+        input.0.push(Span::styled(
+            app.ghost_text(),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    let text = vec![
+        input,
         Spans::from(Span::styled(
             get_command_help_text(&app.command),
             Style::default()
                 .add_modifier(Modifier::ITALIC)
                 .fg(Color::LightBlue),
         )),
+        Spans::from(candidates),
     ];
     let input = Paragraph::new(text)
         .style(match app.mode {
@@ -329,3 +767,93 @@ fn get_command<'a>(app: &'a UserInterface) -> Paragraph<'a> {
         );
     input
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{JsonStorage, MemoryStorage};
+    use crate::model::{BookingType, Category, Recurrence, RecurringBooking};
+    use crate::testutil::TempDir;
+    use tui::backend::TestBackend;
+
+    #[test]
+    fn test_missing_exchange_rate_is_shown() {
+        let storage = MemoryStorage::default();
+        storage.add_category(Category::new("Food", "f")).unwrap();
+        let netflix = RecurringBooking::new(
+            "Netflix",
+            BookingType::Expense,
+            "f",
+            "12".parse().unwrap(),
+            Recurrence::monthly(),
+        )
+        .in_currency("CHF");
+        storage.add_recurring(netflix).unwrap();
+
+        let mut app = UserInterface::new(Box::new(storage), None).unwrap();
+        assert_eq!(app.data_error, "No exchange rate for CHF");
+        let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+        for index in 0..app.tabs.len() {
+            app.index = index;
+            terminal.draw(|f| ui(f, &app).unwrap()).unwrap();
+            let screen: String = terminal
+                .backend()
+                .buffer()
+                .content()
+                .iter()
+                .map(|c| c.symbol.as_str())
+                .collect();
+            assert!(screen.contains("No exchange rate for CHF"), "{}", screen);
+        }
+    }
+
+    #[test]
+    fn test_broken_settings_tab_is_shown() {
+        let dir = TempDir::new("ui-settings");
+        let storage = JsonStorage::new(&dir).unwrap();
+        let mut app = UserInterface::new(Box::new(storage), None).unwrap();
+        app.index = 2;
+        for file in ["settings.json", "data_categories.json"] {
+            std::fs::write(dir.join(file), "[{").unwrap();
+            let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+            terminal.draw(|f| ui(f, &app).unwrap()).unwrap();
+            let screen: String = terminal
+                .backend()
+                .buffer()
+                .content()
+                .iter()
+                .map(|c| c.symbol.as_str())
+                .collect();
+            assert!(screen.contains("EOF while parsing"), "{}", screen);
+            std::fs::write(dir.join(file), "[]").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_income_is_shown_per_month() {
+        let storage = MemoryStorage::default();
+        commands::handle_command("ac Salary s", &storage).unwrap();
+        commands::handle_command("ari Pay s 1000 biweekly", &storage).unwrap();
+
+        let app = UserInterface::new(Box::new(storage), None).unwrap();
+        assert_eq!(app.dataservice.total_income, "2174.06".parse().unwrap());
+        let mut terminal = Terminal::new(TestBackend::new(180, 40)).unwrap();
+        terminal.draw(|f| ui(f, &app).unwrap()).unwrap();
+        let buffer = terminal.backend().buffer();
+        let lines: Vec<String> = (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer.get(x, y).symbol.as_str())
+                    .collect()
+            })
+            .collect();
+        let row = lines.iter().find(|l| l.contains("Pay")).unwrap();
+        assert!(
+            row.contains("2174.06") && row.contains("1000.00"),
+            "{}",
+            row
+        );
+        let sum = lines.iter().find(|l| l.contains(" Sum ")).unwrap();
+        assert!(sum.contains("2174.06"), "{}", sum);
+    }
+}
@@ -1,6 +1,6 @@
-use crate::dataservice::CategorySum;
-use crate::model::{BookingType, RecurringBooking, RecurringType};
-use crate::ui::CURRENCY_SYMBOL;
+use crate::dataservice::{CategoryNode, CategorySum};
+use crate::model::{BookingType, RecurringBooking};
+use crate::money::Money;
 use anyhow::Result;
 use tui::layout::{Layout, Rect};
 use tui::{
@@ -11,7 +11,7 @@ use tui::{
     Frame,
 };
 
-use super::{UserInterface, CATEGORY_TOKEN_MAP};
+use super::{Selection, UserInterface};
 
 pub fn render<B: Backend>(f: &mut Frame<B>, chunk: Rect, app: &UserInterface) -> Result<()> {
     let chunks = Layout::default()
@@ -40,10 +40,14 @@ pub fn render<B: Backend>(f: &mut Frame<B>, chunk: Rect, app: &UserInterface) ->
         .split(chunks[0]);
 
     let calc_entries = app.dataservice.calculate_reccuring_categorie_sums()?;
-    f.render_widget(render_calc_table(calc_entries), col1[0]);
+    f.render_widget(render_calc_table(calc_entries, app), col1[0]);
 
     let income_entries = app.dataservice.get_recurring(BookingType::Income)?;
-    f.render_widget(render_income_table(&income_entries), col1[1]);
+    f.render_stateful_widget(
+        render_income_table(&income_entries, app)?,
+        col1[1],
+        &mut app.table_state(0, income_entries.len()),
+    );
 
     //  Col 2 and Col 3 - Render each category in its own box
     let col2 = Layout::default()
@@ -70,38 +74,37 @@ pub fn render<B: Backend>(f: &mut Frame<B>, chunk: Rect, app: &UserInterface) ->
         )
         .split(chunks[2]);
 
-    let rec_entries = app.dataservice.get_recurring(BookingType::Expense).unwrap();
-
-    // get all categories distinct
-    let mut categories: Vec<String> = rec_entries
-        .iter()
-        .map(|c| c.category_token.to_string())
-        .collect();
-    categories.sort();
-    categories.dedup();
-
     // Foreach categorie render a box, build table with expenses for it and render it into a col.
     // Start top left, then top right, then second row left, second row right etc.
     let mut widget_col = 0;
     let mut widget_row = 0;
-    for cat in categories {
-        let rec: Vec<&&RecurringBooking> = rec_entries
-            .iter()
-            .filter(|c| c.category_token == cat)
-            .collect();
-
-        let cat_name = CATEGORY_TOKEN_MAP.get(&cat).unwrap().to_string();
+    let mut offset = income_entries.len();
+    for (root, rows) in expense_groups(app)? {
+        // Unknown tokens are reported and repaired by `bum check`
+        let cat_name = match app.dataservice.category_name(root.token()) {
+            Some(name) => name.to_string(),
+            None => format!("{} (unknown category)", root.token()),
+        };
 
         match widget_col {
             0 => {
-                f.render_widget(render_expense_table(&rec, cat_name), col2[widget_row]);
+                f.render_stateful_widget(
+                    render_expense_table(&rows, &root, cat_name, app)?,
+                    col2[widget_row],
+                    &mut app.table_state(offset, rows.len()),
+                );
             }
             1 => {
-                f.render_widget(render_expense_table(&rec, cat_name), col3[widget_row]);
+                f.render_stateful_widget(
+                    render_expense_table(&rows, &root, cat_name, app)?,
+                    col3[widget_row],
+                    &mut app.table_state(offset, rows.len()),
+                );
             }
             _ => panic!("Invalid col"),
         }
 
+        offset += rows.len();
         if widget_col == 0 {
             widget_col = 1;
         } else {
@@ -112,48 +115,164 @@ pub fn render<B: Backend>(f: &mut Frame<B>, chunk: Rect, app: &UserInterface) ->
     Ok(())
 }
 
-fn render_expense_table<'a>(items: &Vec<&&RecurringBooking>, title: String) -> Table<'a> {
-    let mut sum: f32 = 0.0;
+/// A row of an expense table.
+enum ExpenseRow<'a> {
+    /// A subcategory with its subtotal.
+    Category(CategoryNode<'a>),
+    /// A recurring item, indented by the depth of its category.
+    Item(&'a RecurringBooking, usize),
+}
+
+/// Recurring expenses with a table per top-level category, in the order the tables are rendered.
+/// Items of subcategories follow their subcategory, unless it or a category above it is collapsed.
+fn expense_groups<'a>(
+    app: &'a UserInterface,
+) -> Result<Vec<(CategoryNode<'a>, Vec<ExpenseRow<'a>>)>> {
+    let expenses = app.dataservice.get_recurring(BookingType::Expense)?;
+    let mut groups: Vec<(CategoryNode, Vec<ExpenseRow>)> = vec![];
+    for mut node in app.dataservice.category_tree(expenses)? {
+        let depth = node.depth();
+        let items: Vec<ExpenseRow> = node
+            .items
+            .drain(..)
+            .map(|r| ExpenseRow::Item(r, depth))
+            .collect();
+        match groups.last_mut() {
+            Some((_, rows)) if depth > 0 => {
+                if hidden(app, &node.path) {
+                    continue;
+                }
+                let collapsed = app.collapsed.contains(node.token());
+                rows.push(ExpenseRow::Category(node));
+                if !collapsed {
+                    rows.extend(items);
+                }
+            }
+            _ => groups.push((node, items)),
+        }
+    }
+    Ok(groups)
+}
+
+/// Whether a collapsed category between the top-level one and the category at the end of `path`
+/// hides it.
+fn hidden(app: &UserInterface, path: &[String]) -> bool {
+    path.len() > 2
+        && path[1..path.len() - 1]
+            .iter()
+            .any(|t| app.collapsed.contains(t))
+}
+
+/// The rows that can be selected, top to bottom and left to right.
+pub fn selectable(app: &UserInterface) -> Result<Vec<Selection>> {
+    let mut rows: Vec<Selection> = app
+        .dataservice
+        .get_recurring(BookingType::Income)?
+        .iter()
+        .map(|r| Selection::Recurring(r.id))
+        .collect();
+    for (_, group) in expense_groups(app)? {
+        rows.extend(group.iter().map(|row| {
+            match row {
+                ExpenseRow::Category(node) => Selection::Category(
+                    app.dataservice
+                        .category(node.token())
+                        .map(|c| c.id)
+                        .unwrap_or_default(),
+                ),
+                ExpenseRow::Item(r, _) => Selection::Recurring(r.id),
+            }
+        }));
+    }
+    Ok(rows)
+}
+
+fn render_expense_table<'a>(
+    rows: &[ExpenseRow],
+    root: &CategoryNode,
+    title: String,
+    app: &UserInterface,
+) -> Result<Table<'a>> {
+    let subtotal = Style::default().fg(Color::Cyan);
     let mut expenses = vec![];
 
-    for b in items {
-        let mut cells = vec![Cell::from(b.name.to_string())];
+    for row in rows {
+        let (b, indent) = match row {
+            ExpenseRow::Category(node) => {
+                let marker = if app.collapsed.contains(node.token()) {
+                    "▸"
+                } else {
+                    "▾"
+                };
+                let name = app
+                    .dataservice
+                    .category_name(node.token())
+                    .unwrap_or(node.token());
+                expenses.push(Row::new(vec![
+                    Cell::default(),
+                    Cell::from(format!(
+                        "{}{} {}",
+                        "  ".repeat(node.depth() - 1),
+                        marker,
+                        name
+                    ))
+                    .style(subtotal),
+                    Cell::from(format!(
+                        "{} {}",
+                        -node.total, app.dataservice.currency_symbol
+                    ))
+                    .style(subtotal),
+                ]));
+                continue;
+            }
+            ExpenseRow::Item(b, indent) => (b, *indent),
+        };
+        let mut cells = vec![
+            Cell::from(b.id.to_string()),
+            Cell::from(format!("{}{}", "  ".repeat(indent), b.name)),
+        ];
 
-        if b.rate_type == RecurringType::Yearly {
-            let monthly_amount = b.amount / 12.0;
-            sum += monthly_amount;
+        let monthly_amount = b.recurrence.monthly_amount(b.amount);
+        cells.push(Cell::from(app.format_amount(monthly_amount, &b.currency)));
+        if b.recurrence.is_monthly() {
+            cells.push(Cell::default());
+        } else {
             cells.push(Cell::from(format!(
-                "{:.2} {}",
-                monthly_amount, *CURRENCY_SYMBOL
+                "{} / {}",
+                app.format_amount(b.amount, &b.currency),
+                b.recurrence
             )));
-            cells.push(Cell::from(format!("{:.2} {}", b.amount, *CURRENCY_SYMBOL)));
-            cells.push(Cell::from(format!("{}", b.rate_type)));
-        } else {
-            sum += b.amount;
-            cells.push(Cell::from(format!("{:.2} {}", b.amount, *CURRENCY_SYMBOL)));
-            cells.push(Cell::default());
-            cells.push(Cell::from("-".to_string()));
         }
+        cells.push(Cell::from(
+            b.next_due
+                .map(|d| d.format("%d.%m.%y").to_string())
+                .unwrap_or_else(|| "-".to_string()),
+        ));
 
         expenses.push(Row::new(cells));
     }
 
     expenses.push(Row::new(vec![Cell::default()]));
     expenses.push(Row::new(vec![
-        Cell::from(" Sum ").style(Style::default().fg(Color::Cyan)),
-        Cell::from(format!("{:.2} {}", sum, *CURRENCY_SYMBOL))
-            .style(Style::default().fg(Color::Cyan)),
+        Cell::default(),
+        Cell::from(" Sum ").style(subtotal),
+        Cell::from(format!(
+            "{} {}",
+            -root.total, app.dataservice.currency_symbol
+        ))
+        .style(subtotal),
         Cell::default(),
     ]));
 
     let t = Table::new(expenses)
         .style(Style::default().fg(Color::White))
         .header(
-            Row::new(vec!["Name", "Monthly", "Yearly", "Due"])
+            Row::new(vec!["#", "Name", "Monthly", "Billed", "Due"])
                 .style(Style::default().fg(Color::Yellow)),
         )
         .widths(&[
-            Constraint::Percentage(40),
+            Constraint::Length(4),
+            Constraint::Percentage(35),
             Constraint::Percentage(20),
             Constraint::Percentage(20),
             Constraint::Percentage(20),
@@ -168,30 +287,62 @@ fn render_expense_table<'a>(items: &Vec<&&RecurringBooking>, title: String) -> T
                 .border_type(BorderType::Plain)
                 .border_style(Style::default().fg(Color::LightRed)),
         );
-    t
+    Ok(t)
 }
 
-fn render_income_table<'a>(items: &Vec<&RecurringBooking>) -> Table<'a> {
-    let sum: f32 = items.iter().map(|r| r.amount).sum();
+fn render_income_table<'a>(
+    items: &Vec<&RecurringBooking>,
+    app: &UserInterface,
+) -> Result<Table<'a>> {
+    // Monthly shares, like the totals of the overview
+    let sum: Money = items
+        .iter()
+        .map(|r| {
+            app.dataservice
+                .in_base(r.recurrence.monthly_amount(r.amount), &r.currency)
+        })
+        .sum::<Result<Money>>()?;
     let mut expenses = vec![];
 
     for b in items {
-        let mut cells = vec![Cell::from(b.name.to_string())];
-        cells.push(Cell::from(format!("{:.2} {}", b.amount, *CURRENCY_SYMBOL)));
+        let billed = if b.recurrence.is_monthly() {
+            String::new()
+        } else {
+            format!(
+                "{} / {}",
+                app.format_amount(b.amount, &b.currency),
+                b.recurrence
+            )
+        };
+        let cells = vec![
+            Cell::from(b.id.to_string()),
+            Cell::from(b.name.to_string()),
+            Cell::from(app.format_amount(b.recurrence.monthly_amount(b.amount), &b.currency)),
+            Cell::from(billed),
+        ];
         expenses.push(Row::new(cells));
     }
 
     expenses.push(Row::new(vec![Cell::default()]));
     expenses.push(Row::new(vec![
+        Cell::default(),
         Cell::from(" Sum ").style(Style::default().fg(Color::Cyan)),
-        Cell::from(format!("{:.2} {}", sum, *CURRENCY_SYMBOL))
+        Cell::from(format!("{} {}", sum, app.dataservice.currency_symbol))
             .style(Style::default().fg(Color::Cyan)),
     ]));
 
     let t = Table::new(expenses)
         .style(Style::default().fg(Color::White))
-        .header(Row::new(vec!["Name", "Monthly"]).style(Style::default().fg(Color::Yellow)))
-        .widths(&[Constraint::Percentage(70), Constraint::Percentage(30)])
+        .header(
+            Row::new(vec!["#", "Name", "Monthly", "Billed"])
+                .style(Style::default().fg(Color::Yellow)),
+        )
+        .widths(&[
+            Constraint::Length(4),
+            Constraint::Percentage(35),
+            Constraint::Percentage(25),
+            Constraint::Percentage(35),
+        ])
         .column_spacing(0)
         .highlight_style(Style::default().add_modifier(Modifier::BOLD))
         .highlight_symbol(">>")
@@ -202,17 +353,25 @@ fn render_income_table<'a>(items: &Vec<&RecurringBooking>) -> Table<'a> {
                 .border_type(BorderType::Plain)
                 .border_style(Style::default().fg(Color::LightGreen)),
         );
-    t
+    Ok(t)
 }
 
-fn render_calc_table<'a>(items: Vec<CategorySum>) -> Table<'a> {
-    let sum: f32 = items.iter().map(|r| r.amount).sum();
+fn render_calc_table<'a>(items: Vec<CategorySum>, app: &UserInterface) -> Table<'a> {
+    let currency_symbol = &app.dataservice.currency_symbol;
+    // Subcategories are already part of the sum of their top-level category
+    let sum: Money = items
+        .iter()
+        .filter(|r| r.path.len() <= 1)
+        .map(|r| r.amount)
+        .sum();
     let mut items: Vec<_> = items
         .iter()
+        .filter(|b| !hidden(app, &b.path))
         .map(|b| {
+            let indent = "  ".repeat(b.path.len().saturating_sub(1));
             Row::new(vec![
-                Cell::from(b.name.to_string()),
-                Cell::from(format!("{:.2} {}", b.amount, *CURRENCY_SYMBOL)),
+                Cell::from(format!("{}{}", indent, b.name)),
+                Cell::from(format!("{} {}", b.amount, currency_symbol)),
             ])
         })
         .collect();
@@ -220,13 +379,13 @@ fn render_calc_table<'a>(items: Vec<CategorySum>) -> Table<'a> {
     items.push(Row::new(vec![Cell::default()]));
     items.push(Row::new(vec![
         Cell::from(" Budget Left ").style(Style::default().fg(Color::Cyan)),
-        Cell::from(format!("{:0.2} €", sum)).style(Style::default().fg(Color::Cyan)),
+        Cell::from(format!("{} {}", sum, currency_symbol)).style(Style::default().fg(Color::Cyan)),
     ]));
 
     let t = Table::new(items)
         .style(Style::default().fg(Color::White))
         .header(Row::new(vec!["Name", "Amount"]).style(Style::default().fg(Color::Yellow)))
-        .widths(&[Constraint::Length(10), Constraint::Length(10)])
+        .widths(&[Constraint::Length(16), Constraint::Length(10)])
         .column_spacing(5)
         .highlight_style(Style::default().add_modifier(Modifier::BOLD))
         .highlight_symbol(">>")
@@ -8,11 +8,11 @@ use tui::{
     Frame,
 };
 
-use crate::db;
+use crate::db::Storage;
 
-use super::UserInterface;
+use super::{Selection, UserInterface};
 
-pub fn render<B: Backend>(f: &mut Frame<B>, chunk: Rect, _app: &UserInterface) -> Result<()> {
+pub fn render<B: Backend>(f: &mut Frame<B>, chunk: Rect, app: &UserInterface) -> Result<()> {
     let setting_chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints(
@@ -26,18 +26,29 @@ pub fn render<B: Backend>(f: &mut Frame<B>, chunk: Rect, _app: &UserInterface) -
         )
         .split(chunk);
 
-    let settings = render_settings_table();
+    let settings = render_settings_table(app.storage.as_ref())?;
     f.render_widget(settings, setting_chunks[0]);
 
-    let categories = render_category_table();
-    f.render_widget(categories, setting_chunks[1]);
+    let categories = render_category_table(app.storage.as_ref())?;
+    let mut state = app.table_state(0, selectable(app)?.len());
+    f.render_stateful_widget(categories, setting_chunks[1], &mut state);
 
     Ok(())
 }
 
-fn render_settings_table<'a>() -> Table<'a> {
-    let items: Vec<_> = db::get_settings()
-        .unwrap()
+/// The rows that can be selected, the categories.
+pub fn selectable(app: &UserInterface) -> Result<Vec<Selection>> {
+    Ok(app
+        .storage
+        .get_categories()?
+        .iter()
+        .map(|c| Selection::Category(c.id))
+        .collect())
+}
+
+fn render_settings_table<'a>(storage: &dyn Storage) -> Result<Table<'a>> {
+    let items: Vec<_> = storage
+        .get_settings()?
         .iter()
         .map(|b| {
             Row::new(vec![
@@ -59,25 +70,37 @@ fn render_settings_table<'a>() -> Table<'a> {
                 .title(" Settings ")
                 .border_type(BorderType::Plain),
         );
-    t
+    Ok(t)
 }
 
-fn render_category_table<'a>() -> Table<'a> {
+fn render_category_table<'a>(storage: &dyn Storage) -> Result<Table<'a>> {
     // active
-    let items: Vec<_> = db::get_categories()
-        .unwrap()
+    let items: Vec<_> = storage
+        .get_categories()?
         .iter()
         .map(|b| {
             Row::new(vec![
+                Cell::from(b.id.to_string()),
                 Cell::from(b.token.to_string()),
                 Cell::from(b.name.to_string()),
+                Cell::from(b.parent.clone().unwrap_or_default()),
+                Cell::from(b.limit.map(|l| l.to_string()).unwrap_or_default()),
             ])
         })
         .collect();
     let t = Table::new(items)
         .style(Style::default().fg(Color::White))
-        .header(Row::new(vec!["Id", "Name"]).style(Style::default().fg(Color::Yellow)))
-        .widths(&[Constraint::Length(3), Constraint::Length(10)])
+        .header(
+            Row::new(vec!["#", "Token", "Name", "Parent", "Limit"])
+                .style(Style::default().fg(Color::Yellow)),
+        )
+        .widths(&[
+            Constraint::Length(4),
+            Constraint::Length(5),
+            Constraint::Length(10),
+            Constraint::Length(6),
+            Constraint::Length(10),
+        ])
         .column_spacing(5)
         .highlight_style(Style::default().add_modifier(Modifier::BOLD))
         .highlight_symbol(">>")
@@ -87,5 +110,5 @@ fn render_category_table<'a>() -> Table<'a> {
                 .title(" Categories ")
                 .border_type(BorderType::Plain),
         );
-    t
+    Ok(t)
 }